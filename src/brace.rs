@@ -0,0 +1,174 @@
+/// Expands the brace expressions in a word, like `a{b,c}` or `{1..5}`, into the words they
+/// stand for, in order. Braces that don't form a list or a range are left as they are, as are
+/// braces and commas escaped with a backslash.
+pub fn expand(word: &str) -> Vec<String> {
+    let chars: Vec<_> = word.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => {
+                if let Some((close, alternatives)) = alternatives(&chars, i) {
+                    let prefix: String = chars[..i].iter().collect();
+                    let suffixes = expand(&chars[close + 1..].iter().collect::<String>());
+                    let mut words = vec![];
+                    for middle in alternatives.iter().flat_map(|a| expand(a)) {
+                        for suffix in &suffixes {
+                            words.push(format!("{}{}{}", prefix, middle, suffix));
+                        }
+                    }
+                    return words;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![word.to_string()]
+}
+
+/// Finds the `}` closing the brace at `open` and what the expression between them expands to.
+/// Returns `None` if it isn't closed or is neither a comma-separated list nor a range.
+fn alternatives(chars: &[char], open: usize) -> Option<(usize, Vec<String>)> {
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut i = open + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' => break,
+            ',' if depth == 0 => commas.push(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    if i >= chars.len() {
+        return None;
+    }
+
+    if commas.is_empty() {
+        let body: String = chars[open + 1..i].iter().collect();
+        return sequence(&body).map(|items| (i, items));
+    }
+    let bounds: Vec<_> = std::iter::once(open)
+        .chain(commas)
+        .chain(std::iter::once(i))
+        .collect();
+    let items = bounds
+        .windows(2)
+        .map(|pair| chars[pair[0] + 1..pair[1]].iter().collect())
+        .collect();
+    Some((i, items))
+}
+
+/// Expands a range like `1..5`, `0..10..2` or `a..e`. Numbers written with leading zeros
+/// are padded to the same width.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<_> = body.split("..").collect();
+    let step = match parts.len() {
+        2 => 1,
+        3 => parts[2].parse::<i64>().ok()?.unsigned_abs().max(1),
+        _ => return None,
+    };
+    let (start, end) = (parts[0], parts[1]);
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| {
+            let digits = s.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = match padded(start) || padded(end) {
+            true => start.len().max(end.len()),
+            false => 0,
+        };
+        return Some(
+            range(first, last, step)?
+                .map(|n| match n < 0 {
+                    true => format!("-{:0>1$}", n.unsigned_abs(), width.saturating_sub(1)),
+                    false => format!("{:0>1$}", n, width),
+                })
+                .collect(),
+        );
+    }
+
+    let letter = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => Some(c as i64),
+            _ => None,
+        }
+    };
+    let (first, last) = (letter(start)?, letter(end)?);
+    Some(
+        range(first, last, step)?
+            .map(|c| char::from(c as u8).to_string())
+            .collect(),
+    )
+}
+
+/// The values from `first` to `last` inclusive, `step` apart, counting down if `last` is
+/// the smaller. Returns `None` for a range too big to count.
+fn range(first: i64, last: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = (first.abs_diff(last) / step).checked_add(1)?;
+    let descending = first > last;
+    Some((0..count).map(move |i| {
+        // Every value lies between `first` and `last`, so the offset always fits.
+        let offset = i * step;
+        match descending {
+            true => first.wrapping_sub_unsigned(offset),
+            false => first.wrapping_add_unsigned(offset),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists() {
+        assert_eq!(expand("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand("{x,,y}"), ["x", "", "y"]);
+        assert_eq!(expand("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(expand("{1..4}"), ["1", "2", "3", "4"]);
+        assert_eq!(expand("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand("{0..10..5}"), ["0", "5", "10"]);
+        assert_eq!(expand("{08..10}"), ["08", "09", "10"]);
+        assert_eq!(expand("{-2..1}"), ["-2", "-1", "0", "1"]);
+        assert_eq!(expand("{a..c}"), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(expand("{a,b{1,2}}"), ["a", "b1", "b2"]);
+        assert_eq!(expand("x{a,{1..2}}"), ["xa", "x1", "x2"]);
+    }
+
+    #[test]
+    fn literal_fallback() {
+        assert_eq!(expand("{a}"), ["{a}"]);
+        assert_eq!(expand("{a,b"), ["{a,b"]);
+        assert_eq!(expand("\\{a,b}"), ["\\{a,b}"]);
+        assert_eq!(expand("{1..a}"), ["{1..a}"]);
+    }
+
+    #[test]
+    fn extreme_ranges() {
+        assert_eq!(
+            expand("{-9223372036854775808..-9223372036854775807}"),
+            ["-9223372036854775808", "-9223372036854775807"]
+        );
+        assert_eq!(
+            expand("{9223372036854775807..9223372036854775806}"),
+            ["9223372036854775807", "9223372036854775806"]
+        );
+        let whole = "{-9223372036854775808..9223372036854775807}";
+        assert_eq!(expand(whole), [whole]);
+    }
+}
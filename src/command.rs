@@ -1,83 +1,1355 @@
 use anyhow::Result;
-use std::{env, fmt::Display, io::Write, path::PathBuf, process};
-use strum::EnumString;
+use std::{
+    env,
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    iter, mem,
+    os::unix::{
+        fs::{OpenOptionsExt, PermissionsExt},
+        process::{CommandExt, ExitStatusExt},
+    },
+    path::{Component, Path, PathBuf},
+    process::{self, Child, ExitStatus, Stdio},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use strum::{EnumIter, EnumString, IntoEnumIterator, IntoStaticStr};
 
-#[derive(EnumString)]
-#[strum(ascii_case_insensitive)]
+use crate::{
+    jobs::{self, JobState},
+    pipeline::pipe,
+    prompt, signal,
+    state::{Options, Terminal},
+    token::is_name,
+};
+
+#[derive(EnumString, EnumIter, IntoStaticStr)]
+#[strum(ascii_case_insensitive, serialize_all = "lowercase")]
 enum Builtin {
     Exit,
     Echo,
+    Printf,
     Type,
     Pwd,
     Cd,
+    Pushd,
+    Popd,
+    Dirs,
+    Export,
+    History,
+    Fc,
+    Jobs,
+    Fg,
+    Bg,
+    Wait,
+    Kill,
+    Alias,
+    Unalias,
+    Unset,
+    Set,
+    Shopt,
+    Rehash,
+    Read,
+    Env,
+    True,
+    False,
+    #[strum(serialize = ":")]
+    Colon,
+    #[strum(serialize = "test", serialize = "[")]
+    Test,
+    #[strum(serialize = "source", serialize = ".")]
+    Source,
+    Command,
+    Help,
+}
+
+impl Builtin {
+    /// How the builtin is invoked and what it does, for `help`. The first line of the
+    /// description is the summary `help` lists.
+    fn help(&self) -> (&'static str, &'static str) {
+        match self {
+            Builtin::Exit => (
+                "exit [n]",
+                "Exit the shell with status N, or that of the last command.\n\
+                 With jobs left, the first try only warns about them.",
+            ),
+            Builtin::Echo => (
+                "echo [-neE] [arg ...]",
+                "Write the arguments separated by spaces.\n\
+                 -n leaves off the trailing newline, -e interprets backslash escapes such as\n\
+                 \\n and \\t, and -E turns them back off.",
+            ),
+            Builtin::Printf => (
+                "printf format [arguments]",
+                "Write the arguments as the format describes.\n\
+                 The format understands %s, %d, %x, %c and %%, with an optional - or 0 flag\n\
+                 and a width, and backslash escapes. It is used again while arguments are left.",
+            ),
+            Builtin::Type => ("type name [name ...]", "Tell how each name would be run."),
+            Builtin::Pwd => ("pwd", "Print the current directory."),
+            Builtin::Cd => (
+                "cd [dir]",
+                "Change the current directory.\n\
+                 Without DIR it goes to HOME, and `cd -' goes back to the previous directory,\n\
+                 OLDPWD. A leading ~ stands for HOME.",
+            ),
+            Builtin::Pushd => (
+                "pushd [dir]",
+                "Change to DIR, keeping the current directory on the stack.\n\
+                 Without DIR it swaps the current directory with the top of the stack.",
+            ),
+            Builtin::Popd => (
+                "popd",
+                "Change to the directory on top of the stack and remove it.",
+            ),
+            Builtin::Dirs => ("dirs", "List the current directory and the stack."),
+            Builtin::Export => (
+                "export [name[=value] ...]",
+                "Put variables in the environment of the commands run.\n\
+                 Without arguments it lists the exported variables.",
+            ),
+            Builtin::History => (
+                "history [-c] [n] or history -r|-w|-a [file]",
+                "List the commands entered, or the last N of them.\n\
+                 -c clears the history. -r reads the lines of FILE into it, -w writes all of\n\
+                 it to FILE and -a adds what isn't in a file yet to the end of FILE. FILE\n\
+                 defaults to HISTFILE.",
+            ),
+            Builtin::Fc => (
+                "fc [-e editor] [-lnr] [first] [last]",
+                "List commands from the history, or edit them and run the result.\n\
+                 FIRST and LAST are entry numbers, negative ones counting back, or the start\n\
+                 of a command. -l lists the last 16 commands by default, -n leaves out the\n\
+                 numbers and -r reverses the order. Otherwise the last command is opened in\n\
+                 EDITOR, FCEDIT or the editor given with -e, and run once it is saved.",
+            ),
+            Builtin::Jobs => ("jobs", "List the background and stopped jobs."),
+            Builtin::Fg => ("fg [job]", "Bring a job to the foreground."),
+            Builtin::Bg => ("bg [job]", "Resume a stopped job in the background."),
+            Builtin::Wait => (
+                "wait [pid | job ...]",
+                "Wait for jobs to finish and return the status of the last one.\n\
+                 Without arguments it waits for every job and returns 0.",
+            ),
+            Builtin::Kill => (
+                "kill [-s sig | -sig] pid | job ...",
+                "Send a signal to processes or jobs.\n\
+                 The signal is TERM unless given by name, like -KILL or -s INT, or number.\n\
+                 `kill -l' lists the signal names, or names the signals given by number.",
+            ),
+            Builtin::Alias => (
+                "alias [name[=value] ...]",
+                "Define aliases, or print them.\n\
+                 Without arguments it lists every alias.",
+            ),
+            Builtin::Unalias => (
+                "unalias [-a] name [name ...]",
+                "Remove aliases.\n\
+                 -a removes all of them.",
+            ),
+            Builtin::Unset => ("unset name [name ...]", "Remove variables."),
+            Builtin::Set => (
+                "set [-Cex] [+Cex] [-o name] [+o name]",
+                "Turn shell options on with - or off with +.\n\
+                 -e (errexit) stops at the first command that fails, -x (xtrace) prints\n\
+                 each command before it runs and -C (noclobber) keeps > from overwriting\n\
+                 files. Without arguments it lists every variable.",
+            ),
+            Builtin::Shopt => (
+                "shopt [-su] [name ...]",
+                "Turn optional shell behaviour on with -s or off with -u.\n\
+                 no_empty_cmd_completion keeps Tab on an empty line from listing every\n\
+                 command. Without -s or -u it shows whether each NAME, or every option, is\n\
+                 on, and fails if one is off.",
+            ),
+            Builtin::Rehash => (
+                "rehash",
+                "Forget the programs found on PATH for completion.",
+            ),
+            Builtin::Read => (
+                "read [-r] [name ...]",
+                "Read a line and split it into variables.\n\
+                 The line is split on IFS, and the last NAME takes whatever is left over.\n\
+                 Without a NAME the line goes in REPLY. -r keeps backslashes as they are.",
+            ),
+            Builtin::Env => (
+                "env [name=value ...] [command [arg ...]]",
+                "Run a command with variables added to its environment.\n\
+                 Without a command it lists the environment.",
+            ),
+            Builtin::True => ("true", "Return a successful status."),
+            Builtin::False => ("false", "Return an unsuccessful status."),
+            Builtin::Colon => (": [arg ...]", "Do nothing, successfully."),
+            Builtin::Test => (
+                "test expr",
+                "Check a condition, returning a successful status if it holds.\n\
+                 -e, -f and -d check that a file exists, is a regular file or is a directory,\n\
+                 -z and -n that a string is empty or not, = and != compare strings, and -eq,\n\
+                 -ne, -lt, -le, -gt and -ge compare integers. ! negates the rest. `[ expr ]'\n\
+                 is another way of writing it.",
+            ),
+            Builtin::Source => (
+                "source file",
+                "Run the commands in FILE in the current shell.\n\
+                 `.' is another name for it.",
+            ),
+            Builtin::Command => (
+                "command [-v] name [arg ...]",
+                "Run a builtin or program even if an alias has its name.\n\
+                 -v prints what each name would run instead.",
+            ),
+            Builtin::Help => ("help [name]", "List the builtins, or describe one of them."),
+        }
+    }
+}
+
+/// Where a command's output stream is sent. Pipes between stages are files too.
+pub enum Output {
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+impl Output {
+    pub fn try_clone(&self) -> io::Result<Output> {
+        Ok(match self {
+            Output::Stdout => Output::Stdout,
+            Output::Stderr => Output::Stderr,
+            Output::File(file) => Output::File(file.try_clone()?),
+        })
+    }
+
+    fn stdio(&self) -> io::Result<Stdio> {
+        Ok(match self {
+            Output::Stdout => io::stdout().into(),
+            Output::Stderr => io::stderr().into(),
+            Output::File(file) => file.try_clone()?.into(),
+        })
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout => io::stdout().write(buf),
+            Output::Stderr => io::stderr().write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().flush(),
+            Output::Stderr => io::stderr().flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+/// Where a command reads its standard input from.
+pub enum Input {
+    Stdin,
+    File(File),
+    /// The read end of the pipe from the stage before.
+    Pipe(File),
+    /// Text held by the shell, like the body of a here-document.
+    Bytes(io::Cursor<Vec<u8>>),
+}
+
+impl Input {
+    fn stdio(&self) -> io::Result<Stdio> {
+        Ok(match self {
+            Input::Stdin => Stdio::inherit(),
+            Input::File(file) | Input::Pipe(file) => file.try_clone()?.into(),
+            Input::Bytes(bytes) => {
+                // A thread feeds the text through a pipe so that a body larger than the
+                // pipe's buffer doesn't block the shell. A child that stops reading early
+                // just ends the thread with a broken pipe.
+                let (read, mut write) = pipe()?;
+                let bytes = bytes.get_ref().clone();
+                thread::spawn(move || write.write_all(&bytes));
+                read.into()
+            }
+        })
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Stdin => io::stdin().read(buf),
+            Input::File(file) | Input::Pipe(file) => file.read(buf),
+            Input::Bytes(bytes) => bytes.read(buf),
+        }
+    }
 }
 
 pub struct Command {
     pub name: String,
     pub args: Vec<String>,
-    pub out: Box<dyn Write>,
-    pub err: Box<dyn Write>,
+    pub input: Input,
+    pub out: Output,
+    pub err: Output,
+    /// Variables added to the environment of this command alone.
+    pub env: Vec<(String, String)>,
 }
 
 impl Command {
-    pub fn execute(mut self) -> Result<()> {
-        match Builtin::try_from(self.name.as_str()) {
-            Ok(builtin) => match builtin {
-                Builtin::Exit => handle_exit(self.args),
-                Builtin::Echo => {
-                    let arg_str = self.args.join(" ");
-                    self.print_out(&arg_str)
-                }
-                Builtin::Type => self.handle_type(),
-                Builtin::Pwd => self.print_out(&env::current_dir()?.display()),
-                Builtin::Cd => self.handle_cd(),
-            },
-            Err(_) => self.run_executable_or_unknown(),
+    pub fn is_builtin(&self) -> bool {
+        Builtin::try_from(self.name.as_str()).is_ok()
+    }
+
+    /// The command as `set -x` shows it, after expansion, with words that would otherwise be
+    /// split or expanded again put in single quotes.
+    pub fn trace(&self) -> String {
+        let assignments = self
+            .env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, quote(value)));
+        let words = std::iter::once(&self.name)
+            .filter(|name| !name.is_empty())
+            .chain(&self.args)
+            .map(|word| quote(word));
+        assignments.chain(words).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Runs the command to completion and returns its exit status. A command with only
+    /// assignments sets shell variables instead.
+    pub fn execute(&mut self, term: &mut Terminal) -> Result<i32> {
+        if self.name.is_empty() {
+            for (name, value) in &self.env {
+                term.set_variable(name, value);
+            }
+            return Ok(0);
+        }
+        let Ok(builtin) = Builtin::try_from(self.name.as_str()) else {
+            return match self.spawn(None, false)? {
+                Ok(mut child) => Ok(exit_code(child.wait()?)),
+                Err(status) => Ok(status),
+            };
+        };
+
+        // Assignments before a builtin only last while it runs.
+        let saved: Vec<_> = self
+            .env
+            .iter()
+            .map(|(name, _)| (name.clone(), env::var_os(name)))
+            .collect();
+        for (name, value) in &self.env {
+            env::set_var(name, value);
+        }
+        let status = self.run_builtin(builtin, term);
+        for (name, value) in saved.into_iter().rev() {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+        status
+    }
+
+    fn run_builtin(&mut self, builtin: Builtin, term: &mut Terminal) -> Result<i32> {
+        match builtin {
+            Builtin::Exit => self.handle_exit(term),
+            Builtin::Echo => self.handle_echo(),
+            Builtin::Printf => self.handle_printf(),
+            Builtin::Type => self.handle_type(term),
+            Builtin::Pwd => {
+                self.print_out(&working_dir()?.display())?;
+                Ok(0)
+            }
+            Builtin::Cd => self.handle_cd(term),
+            Builtin::Pushd => self.handle_pushd(term),
+            Builtin::Popd => self.handle_popd(term),
+            Builtin::Dirs => self.print_dirs(term),
+            Builtin::Export => self.handle_export(term),
+            Builtin::History => self.handle_history(term),
+            Builtin::Fc => self.handle_fc(term),
+            Builtin::Jobs => self.handle_jobs(term),
+            Builtin::Fg => self.handle_fg(term),
+            Builtin::Bg => self.handle_bg(term),
+            Builtin::Wait => self.handle_wait(term),
+            Builtin::Kill => self.handle_kill(term),
+            Builtin::Alias => self.handle_alias(term),
+            Builtin::Unalias => self.handle_unalias(term),
+            Builtin::Unset => self.handle_unset(term),
+            Builtin::Set => self.handle_set(term),
+            Builtin::Shopt => self.handle_shopt(term),
+            Builtin::Rehash => {
+                term.executables.clear();
+                Ok(0)
+            }
+            Builtin::Read => self.handle_read(term),
+            Builtin::Env => self.handle_env(),
+            Builtin::True | Builtin::Colon => Ok(0),
+            Builtin::Test => self.handle_test(),
+            Builtin::False => Ok(1),
+            Builtin::Source => self.handle_source(term),
+            Builtin::Command => self.handle_command(term),
+            Builtin::Help => self.handle_help(),
         }
     }
 
-    fn handle_type(&mut self) -> Result<()> {
-        if let Some(cmd) = self.args.first() {
-            match Builtin::try_from(cmd.as_str()) {
-                Ok(_) => self.print_out(&format!("{} is a shell builtin", cmd))?,
+    fn handle_history(&mut self, term: &mut Terminal) -> Result<i32> {
+        let limit = match self.args.first().map(String::as_str) {
+            None => None,
+            Some("-c") => {
+                term.history.clear();
+                return Ok(0);
+            }
+            Some(flag @ ("-r" | "-w" | "-a")) => {
+                let path = match self.args.get(1) {
+                    Some(path) => PathBuf::from(path),
+                    None => match term.history.path() {
+                        Some(path) => path.to_path_buf(),
+                        None => {
+                            self.print_err(&"history: HISTFILE: not set")?;
+                            return Ok(1);
+                        }
+                    },
+                };
+                let result = match flag {
+                    "-r" => term.history.read(&path, term.history_size()),
+                    "-w" => term.history.write(&path),
+                    _ => term.history.append(&path),
+                };
+                if let Err(e) = result {
+                    self.print_err(&format!("history: {}: {}", path.display(), strerror(&e)))?;
+                    return Ok(1);
+                }
+                return Ok(0);
+            }
+            Some(arg) => match arg.parse() {
+                Ok(limit) => Some(limit),
                 Err(_) => {
-                    if let Some(path) = find_command_path(cmd) {
-                        self.print_out(&format!("{} is {}", cmd, path.display()))?
-                    } else {
-                        self.print_out(&format!("{}: not found", cmd))?
+                    self.print_err(&format!("history: {}: numeric argument required", arg))?;
+                    return Ok(1);
+                }
+            },
+        };
+        term.history.print(&mut self.out, limit)?;
+        Ok(0)
+    }
+
+    /// Lists a range of the history with `-l`, or opens it in an editor and runs what was
+    /// saved. The `fc` command itself is left out of the history it works on; after editing,
+    /// the commands that ran take its place.
+    fn handle_fc(&mut self, term: &mut Terminal) -> Result<i32> {
+        let (mut list, mut numbers, mut reverse, mut editor) = (false, true, false, None);
+        let mut args = self.args.clone().into_iter().peekable();
+        let is_flags = |arg: &String| {
+            arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|c| "elnr".contains(c))
+        };
+        while let Some(flags) = args.next_if(is_flags) {
+            for flag in flags[1..].chars() {
+                match flag {
+                    'l' => list = true,
+                    'n' => numbers = false,
+                    'r' => reverse = true,
+                    _ => editor = args.next(),
+                }
+            }
+        }
+        let specs: Vec<_> = args.collect();
+
+        let current = term.history.pop();
+        let len = term.history.len();
+        // Numbers past either end of the history count as its first or last entry.
+        let resolve = |spec: &str| match spec.parse::<isize>() {
+            Ok(n) if n < 0 => Some(len.saturating_sub(n.unsigned_abs())),
+            Ok(n) => Some((n.max(1) as usize - 1).min(len.saturating_sub(1))),
+            Err(_) => term.history.find(spec),
+        };
+        let range = match (specs.first(), specs.get(1)) {
+            _ if len == 0 => None,
+            (None, _) if list => Some((len.saturating_sub(16), len - 1)),
+            (None, _) => Some((len - 1, len - 1)),
+            (Some(first), None) if list => resolve(first).map(|first| (first, len - 1)),
+            (Some(first), None) => resolve(first).map(|first| (first, first)),
+            (Some(first), Some(last)) => resolve(first).zip(resolve(last)),
+        };
+        let Some((first, last)) = range else {
+            if let Some(current) = current {
+                term.add_history(&current);
+            }
+            self.print_err(&"fc: history specification out of range")?;
+            return Ok(1);
+        };
+        let mut indexes: Vec<_> = (first.min(last)..=first.max(last)).collect();
+        if reverse != (first > last) {
+            indexes.reverse();
+        }
+        let entries: Vec<_> = indexes
+            .iter()
+            .filter_map(|&i| Some((i, term.history.get(i)?.to_string())))
+            .collect();
+
+        if list {
+            if let Some(current) = current {
+                term.add_history(&current);
+            }
+            for (i, entry) in entries {
+                match numbers {
+                    true => self.print_out(&format!("{}\t {}", i + 1, entry))?,
+                    false => self.print_out(&format!("\t {}", entry))?,
+                }
+            }
+            return Ok(0);
+        }
+
+        let editor = editor
+            .or_else(|| term.lookup("FCEDIT"))
+            .or_else(|| term.lookup("EDITOR"))
+            .unwrap_or_else(|| "vi".to_string());
+        let mut script = String::new();
+        for (_, entry) in &entries {
+            script.push_str(entry);
+            script.push('\n');
+        }
+        let (path, mut file) = create_temp_file("fc")?;
+        let written = file.write_all(script.as_bytes());
+        drop(file);
+        if let Err(e) = written {
+            let _ = fs::remove_file(&path);
+            return Err(e.into());
+        }
+        let mut words = editor.split_whitespace();
+        let edited = process::Command::new(words.next().unwrap_or("vi"))
+            .args(words)
+            .arg(&path)
+            .status();
+        let script = fs::read_to_string(&path);
+        // The file was only there for the editor.
+        let _ = fs::remove_file(&path);
+        match edited {
+            Ok(status) if status.success() => {}
+            Ok(status) => return Ok(exit_code(status)),
+            Err(e) => {
+                self.print_err(&format!("fc: {}: {}", editor, strerror(&e)))?;
+                return Ok(127);
+            }
+        }
+
+        let script = script?;
+        for line in script.lines() {
+            self.print_out(&line)?;
+            term.add_history(line);
+        }
+        term.source(&path.to_string_lossy(), &script, &mut self.err)
+    }
+
+    fn handle_alias(&mut self, term: &mut Terminal) -> Result<i32> {
+        if self.args.is_empty() {
+            for (name, value) in &term.aliases {
+                writeln!(self.out, "alias {}={}", name, single_quote(value))?;
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for arg in self.args.clone() {
+            match arg.split_once('=') {
+                Some((name, value)) => {
+                    term.aliases.insert(name.to_string(), value.to_string());
+                }
+                None => match term.aliases.get(&arg) {
+                    Some(value) => writeln!(self.out, "alias {}={}", arg, single_quote(value))?,
+                    None => {
+                        self.print_err(&format!("alias: {}: not found", arg))?;
+                        status = 1;
                     }
+                },
+            }
+        }
+        Ok(status)
+    }
+
+    fn handle_unalias(&mut self, term: &mut Terminal) -> Result<i32> {
+        if self.args.first().is_some_and(|arg| arg == "-a") {
+            term.aliases.clear();
+            return Ok(0);
+        }
+        if self.args.is_empty() {
+            self.print_err(&"unalias: usage: unalias [-a] name [name ...]")?;
+            return Ok(2);
+        }
+
+        let mut status = 0;
+        for arg in self.args.clone() {
+            if term.aliases.remove(&arg).is_none() {
+                self.print_err(&format!("unalias: {}: not found", arg))?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+
+    fn handle_jobs(&mut self, term: &mut Terminal) -> Result<i32> {
+        term.jobs.poll()?;
+        for job in term.jobs.list() {
+            let state = match job.state() {
+                JobState::Running => "Running".to_string(),
+                JobState::Stopped => "Stopped".to_string(),
+                JobState::Done(0) => "Done".to_string(),
+                JobState::Done(status) => format!("Exit {}", status),
+            };
+            let background = if job.state() == JobState::Running {
+                " &"
+            } else {
+                ""
+            };
+            let marker = term.jobs.marker(job.id);
+            writeln!(
+                self.out,
+                "[{}]{}  {:<24}{}{}",
+                job.id, marker, state, job.command, background
+            )?;
+        }
+        // Finished jobs are only listed once.
+        term.jobs.reap()?;
+        Ok(0)
+    }
+
+    /// Resolves the job spec argument of `fg` or `bg`, reporting it if there is no such job.
+    fn find_job(&mut self, term: &Terminal) -> Result<Option<usize>> {
+        let spec = self.args.first().map(String::as_str);
+        let id = term.jobs.find(spec);
+        if id.is_none() {
+            let spec = spec.unwrap_or("current");
+            self.print_err(&format!("{}: {}: no such job", self.name, spec))?;
+        }
+        Ok(id)
+    }
+
+    fn handle_fg(&mut self, term: &mut Terminal) -> Result<i32> {
+        let Some(id) = self.find_job(term)? else {
+            return Ok(1);
+        };
+        let Some(mut job) = term.jobs.remove(id) else {
+            return Ok(1);
+        };
+        self.print_out(&job.command)?;
+        job.resume()?;
+        term.foreground(job)
+    }
+
+    fn handle_bg(&mut self, term: &mut Terminal) -> Result<i32> {
+        let Some(id) = self.find_job(term)? else {
+            return Ok(1);
+        };
+        let marker = term.jobs.marker(id);
+        let Some(job) = term.jobs.get_mut(id) else {
+            return Ok(1);
+        };
+        if job.state() != JobState::Stopped {
+            let msg = format!("bg: job {} already in background", id);
+            self.print_err(&msg)?;
+            return Ok(0);
+        }
+        job.resume()?;
+        let msg = format!("[{}]{} {} &", id, marker, job.command);
+        self.print_out(&msg)?;
+        Ok(0)
+    }
+
+    /// Blocks until each job or process id given has finished, or every job if none are, and
+    /// stops tracking the ones that have. The status is that of the last one waited for, or
+    /// 127 if it isn't a job of this shell.
+    fn handle_wait(&mut self, term: &mut Terminal) -> Result<i32> {
+        let mut ids = vec![];
+        if self.args.is_empty() {
+            ids = term.jobs.list().iter().map(|job| Some(job.id)).collect();
+        }
+        for spec in self.args.clone() {
+            let id = match spec.parse::<u32>() {
+                Ok(pid) => term
+                    .jobs
+                    .find_pid(pid)
+                    .ok_or_else(|| format!("wait: pid {} is not a child of this shell", pid)),
+                Err(_) if spec.starts_with('%') => term
+                    .jobs
+                    .find(Some(&spec))
+                    .ok_or_else(|| format!("wait: {}: no such job", spec)),
+                Err(_) => {
+                    let msg = format!("wait: `{}': not a pid or valid job spec", spec);
+                    self.print_err(&msg)?;
+                    return Ok(2);
+                }
+            };
+            match id {
+                Ok(id) => ids.push(Some(id)),
+                Err(msg) => {
+                    self.print_err(&msg)?;
+                    ids.push(None);
                 }
             }
         }
-        Ok(())
+
+        let mut status = 0;
+        for id in ids {
+            let Some(job) = id.and_then(|id| term.jobs.get_mut(id)) else {
+                status = 127;
+                continue;
+            };
+            job.wait()?;
+            status = match job.state() {
+                JobState::Done(status) => status,
+                JobState::Running | JobState::Stopped => 128 + signal::SIGTSTP,
+            };
+            if let JobState::Done(_) = job.state() {
+                let id = job.id;
+                term.jobs.remove(id);
+            }
+        }
+        // Waiting without arguments only reports whether it could wait at all.
+        if self.args.is_empty() {
+            status = 0;
+        }
+        Ok(status)
     }
 
-    fn handle_cd(&mut self) -> Result<()> {
-        let path = self.args.get(0).map_or("~", String::as_str);
-        let target = if path == "~" {
-            env::var("HOME").unwrap_or_else(|_| "/".to_string())
-        } else {
-            path.to_string()
+    /// Starts an external command connected directly to its configured streams, optionally
+    /// in the given process group (`0` for a new one). A foreground command takes over the
+    /// terminal itself so that it can read from it before the shell gets around to handing
+    /// it over. A command that can't be started gives the status it fails with instead: 127
+    /// if there is no such program and 126 if it can't be run.
+    pub fn spawn(
+        &mut self,
+        group: Option<i32>,
+        foreground: bool,
+    ) -> Result<std::result::Result<Child, i32>> {
+        let program = match find_command_path(&self.name) {
+            Some(path) => path,
+            None if self.name.contains('/') => PathBuf::from(&self.name),
+            None => {
+                self.print_err(&format!("{}: command not found", self.name))?;
+                return Ok(Err(127));
+            }
         };
-        if env::set_current_dir(&target).is_err() {
-            self.print_err(&format!("cd: {}: No such file or directory", target))?;
+        if program.is_dir() {
+            self.print_err(&format!("{}: Is a directory", self.name))?;
+            return Ok(Err(126));
         }
-        Ok(())
+        let mut command = process::Command::new(&program);
+        command
+            .arg0(&self.name)
+            .args(&self.args)
+            .envs(self.env.clone())
+            .stdin(self.input.stdio()?)
+            .stdout(self.out.stdio()?)
+            .stderr(self.err.stdio()?);
+        if let Some(group) = group {
+            command.process_group(group);
+        }
+        // SAFETY: the hook only makes async-signal-safe calls.
+        unsafe {
+            command.pre_exec(move || {
+                if foreground && group.is_some() {
+                    jobs::set_foreground(jobs::current_group());
+                }
+                signal::restore_defaults();
+                Ok(())
+            });
+        }
+        let spawned = command.spawn();
+        match spawned {
+            Ok(child) => Ok(Ok(child)),
+            Err(e) => {
+                self.print_err(&format!("{}: {}", self.name, strerror(&e)))?;
+                let missing = e.kind() == io::ErrorKind::NotFound;
+                Ok(Err(if missing { 127 } else { 126 }))
+            }
+        }
+    }
+
+    /// Describes what each name would run as, checking aliases before builtins and
+    /// builtins before `PATH`, as lookup does.
+    fn handle_type(&mut self, term: &Terminal) -> Result<i32> {
+        let mut status = 0;
+        for cmd in self.args.clone() {
+            if let Some(value) = term.aliases.get(&cmd) {
+                self.print_out(&format!("{} is aliased to `{}'", cmd, value))?;
+            } else if Builtin::try_from(cmd.as_str()).is_ok() {
+                self.print_out(&format!("{} is a shell builtin", cmd))?;
+            } else if let Some(path) = find_command_path(&cmd) {
+                self.print_out(&format!("{} is {}", cmd, path.display()))?;
+            } else {
+                self.print_err(&format!("{}: not found", cmd))?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Runs the command in the arguments as a builtin or a program on `PATH` even if an alias
+    /// has its name. With `-v` it prints what each name would run instead.
+    fn handle_command(&mut self, term: &mut Terminal) -> Result<i32> {
+        if self.args.first().is_some_and(|arg| arg == "-v") {
+            let mut status = 0;
+            for name in self.args.clone().into_iter().skip(1) {
+                if let Some(value) = term.aliases.get(&name) {
+                    self.print_out(&format!("alias {}={}", name, single_quote(value)))?;
+                } else if Builtin::try_from(name.as_str()).is_ok() {
+                    self.print_out(&name)?;
+                } else if let Some(path) = find_command_path(&name) {
+                    self.print_out(&path.display())?;
+                } else {
+                    status = 1;
+                }
+            }
+            return Ok(status);
+        }
+        if self.args.is_empty() {
+            return Ok(0);
+        }
+        self.name = self.args.remove(0);
+        self.execute(term)
+    }
+
+    /// Exits the shell, saving the history first, with the status given taken modulo 256 or
+    /// else the status of the last command. One that isn't a number exits with 2.
+    fn handle_exit(&mut self, term: &mut Terminal) -> Result<i32> {
+        if term.holds_back_exit()? {
+            return Ok(1);
+        }
+        let status = match self.args.as_slice() {
+            [] => term.last_status,
+            [arg] => match arg.trim().parse::<i64>() {
+                Ok(status) => status.rem_euclid(256) as i32,
+                Err(_) => {
+                    self.print_err(&format!("exit: {}: numeric argument required", arg))?;
+                    2
+                }
+            },
+            _ => {
+                self.print_err(&"exit: too many arguments")?;
+                return Ok(1);
+            }
+        };
+        term.exit(status);
+    }
+
+    /// Lists every builtin with a summary of what it does, or describes the ones named.
+    fn handle_help(&mut self) -> Result<i32> {
+        if self.args.is_empty() {
+            let width = Builtin::iter().map(|b| b.help().0.len()).max().unwrap_or(0);
+            for builtin in Builtin::iter() {
+                let (usage, description) = builtin.help();
+                let summary = description.lines().next().unwrap_or_default();
+                self.print_out(&format!("{:<width$}  {}", usage, summary))?;
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for name in self.args.clone() {
+            let Ok(builtin) = Builtin::try_from(name.as_str()) else {
+                self.print_err(&format!("help: no help topics match `{}'", name))?;
+                status = 1;
+                continue;
+            };
+            let (usage, description) = builtin.help();
+            self.print_out(&format!("{}: {}", name, usage))?;
+            for line in description.lines() {
+                self.print_out(&format!("    {}", line))?;
+            }
+        }
+        Ok(status)
     }
 
-    fn run_executable_or_unknown(&mut self) -> Result<()> {
-        if find_command_path(&self.name).is_some() {
-            match process::Command::new(&self.name).args(&self.args).output() {
-                Ok(output) => {
-                    self.out.write_all(&output.stdout)?;
-                    self.err.write_all(&output.stderr)?;
-                    Ok(())
+    /// Changes directory, to `HOME` without an argument and back to `OLDPWD` for `-`. A
+    /// relative directory that isn't in the current one is looked for in each directory of
+    /// `CDPATH` in turn, and the directory found is printed, as it is for `-`.
+    fn handle_cd(&mut self, term: &Terminal) -> Result<i32> {
+        let path = self.args.first().cloned().unwrap_or_default();
+        let searched = search_cdpath(&path, &term.lookup("CDPATH").unwrap_or_default());
+        let target = match path.as_str() {
+            "" => env::var("HOME").unwrap_or_else(|_| "/".to_string()),
+            "-" => match env::var("OLDPWD") {
+                Ok(dir) => dir,
+                Err(_) => {
+                    self.print_err(&"cd: OLDPWD not set")?;
+                    return Ok(1);
                 }
-                Err(e) => self.print_err(&e),
+            },
+            _ => searched.clone().unwrap_or_else(|| path.clone()),
+        };
+        if !self.change_dir("cd", &target)? {
+            return Ok(1);
+        }
+        if path == "-" || searched.is_some() {
+            self.print_out(&working_dir()?.display())?;
+        }
+        Ok(0)
+    }
+
+    /// Changes to `target` the way `cd` does, keeping `PWD` and `OLDPWD` up to date, and
+    /// returns whether it could. A failure is reported as coming from the builtin `name`.
+    fn change_dir(&mut self, name: &str, target: &str) -> Result<bool> {
+        let previous = working_dir().ok();
+        // Like bash, `..` first goes back up the path as typed, even through a symlink, and
+        // only then falls back to the directory's real parent.
+        let logical = previous.as_ref().map(|cwd| normalize(&cwd.join(target)));
+        let changed = match &logical {
+            Some(logical) if env::set_current_dir(logical).is_ok() => Ok(logical.clone()),
+            _ => env::set_current_dir(target).and_then(|()| env::current_dir()),
+        };
+        match changed {
+            Ok(dir) => env::set_var("PWD", dir),
+            Err(e) => {
+                self.print_err(&format!("{}: {}: {}", name, target, strerror(&e)))?;
+                return Ok(false);
+            }
+        }
+        if let Some(previous) = previous {
+            env::set_var("OLDPWD", previous);
+        }
+        Ok(true)
+    }
+
+    /// Prints the arguments separated by spaces. Leading `-n` leaves off the newline, `-e`
+    /// turns on backslash escapes and `-E` turns them back off; `\\c` among the escapes
+    /// stops the output there.
+    fn handle_echo(&mut self) -> Result<i32> {
+        let mut newline = true;
+        let mut escapes = false;
+        let mut words = self.args.as_slice();
+        while let Some(flags) = words.first().and_then(|word| word.strip_prefix('-')) {
+            if flags.is_empty() || !flags.chars().all(|c| "neE".contains(c)) {
+                break;
             }
+            for flag in flags.chars() {
+                match flag {
+                    'n' => newline = false,
+                    'e' => escapes = true,
+                    _ => escapes = false,
+                }
+            }
+            words = &words[1..];
+        }
+
+        let mut text = words.join(" ");
+        if escapes {
+            let (unescaped, stop) = unescape(&text, true);
+            text = unescaped;
+            newline &= !stop;
+        }
+        if newline {
+            text.push('\n');
+        }
+        self.out.write_all(text.as_bytes())?;
+        Ok(0)
+    }
+
+    /// Writes the format in the first argument with its conversions filled in from the rest.
+    fn handle_printf(&mut self) -> Result<i32> {
+        let Some((format, args)) = self.args.split_first() else {
+            self.print_err(&"printf: usage: printf format [arguments]")?;
+            return Ok(2);
+        };
+        let (text, errors) = printf(format, args);
+        self.out.write_all(text.as_bytes())?;
+        for error in &errors {
+            self.print_err(&format!("printf: {}", error))?;
+        }
+        Ok(if errors.is_empty() { 0 } else { 1 })
+    }
+
+    /// Returns 0 if the expression in the arguments holds and 1 if it doesn't, or 2 if it
+    /// can't be understood. Called as `[`, the arguments have to end in `]`.
+    fn handle_test(&mut self) -> Result<i32> {
+        let mut args = self.args.as_slice();
+        if self.name == "[" {
+            match args.split_last() {
+                Some((last, rest)) if last == "]" => args = rest,
+                _ => {
+                    self.print_err(&"[: missing `]'")?;
+                    return Ok(2);
+                }
+            }
+        }
+        match evaluate(args) {
+            Ok(holds) => Ok(if holds { 0 } else { 1 }),
+            Err(e) => {
+                self.print_err(&format!("{}: {}", self.name, e))?;
+                Ok(2)
+            }
+        }
+    }
+
+    /// Turns shell options on with `-` and off with `+`, either by letter as in `set -e` or
+    /// by name as in `set -o errexit`. Without arguments it lists every variable instead.
+    fn handle_set(&mut self, term: &mut Terminal) -> Result<i32> {
+        if self.args.is_empty() {
+            let mut vars: Vec<_> = env::vars()
+                .chain(term.variables.clone())
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            vars.sort();
+            for var in vars {
+                self.print_out(&var)?;
+            }
+            return Ok(0);
+        }
+
+        let mut args = self.args.clone().into_iter();
+        while let Some(arg) = args.next() {
+            let on = match arg.chars().next() {
+                Some('-') => true,
+                Some('+') => false,
+                _ => {
+                    self.print_err(&format!("set: {}: invalid option", arg))?;
+                    return Ok(2);
+                }
+            };
+            if arg[1..] == *"o" {
+                let name = args.next().unwrap_or_default();
+                if !term.options.set_named(&name, on) {
+                    self.print_err(&format!("set: {}: invalid option name", name))?;
+                    return Ok(2);
+                }
+                continue;
+            }
+            for flag in arg[1..].chars() {
+                if !term.options.set_flag(flag, on) {
+                    self.print_err(&format!("set: {}{}: invalid option", &arg[..1], flag))?;
+                    return Ok(2);
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    fn handle_shopt(&mut self, term: &mut Terminal) -> Result<i32> {
+        let mut args = self.args.as_slice();
+        let on = match args.first().map(String::as_str) {
+            Some("-s") => Some(true),
+            Some("-u") => Some(false),
+            Some(flag) if flag.len() > 1 && flag.starts_with('-') => {
+                self.print_err(&format!("shopt: {}: invalid option", flag))?;
+                return Ok(2);
+            }
+            _ => None,
+        };
+        if on.is_some() {
+            args = &args[1..];
+        }
+        let names: Vec<_> = match args {
+            [] => Options::SHOPT_NAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            names => names.to_vec(),
+        };
+
+        let mut status = 0;
+        for name in names {
+            let Some(current) = term.options.shopt(&name) else {
+                self.print_err(&format!("shopt: {}: invalid shell option name", name))?;
+                status = 1;
+                continue;
+            };
+            match on {
+                Some(on) => {
+                    term.options.set_shopt(&name, on);
+                }
+                None => {
+                    let state = if current { "on" } else { "off" };
+                    self.print_out(&format!("{:<15}\t{}", name, state))?;
+                    if !current {
+                        status = 1;
+                    }
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    /// Sends a signal, `TERM` unless one is given, to each process id or job spec in the
+    /// arguments. A stopped job is continued as well so that it can act on the signal.
+    fn handle_kill(&mut self, term: &mut Terminal) -> Result<i32> {
+        let all = self.args.clone();
+        let mut args = all.as_slice();
+        let mut sig = signal::SIGTERM;
+        match args.first().map(String::as_str) {
+            Some("-l") => return self.list_signals(&args[1..]),
+            Some("-s") => {
+                let spec = args.get(1).map_or("", String::as_str);
+                let Some(parsed) = signal::parse(spec) else {
+                    self.print_err(&format!("kill: {}: invalid signal specification", spec))?;
+                    return Ok(1);
+                };
+                sig = parsed;
+                args = &args[2.min(args.len())..];
+            }
+            Some("--") => args = &args[1..],
+            Some(spec) if spec.len() > 1 && spec.starts_with('-') => {
+                let Some(parsed) = signal::parse(&spec[1..]) else {
+                    self.print_err(&format!(
+                        "kill: {}: invalid signal specification",
+                        &spec[1..]
+                    ))?;
+                    return Ok(1);
+                };
+                sig = parsed;
+                args = &args[1..];
+            }
+            _ => {}
+        }
+        if args.is_empty() {
+            self.print_err(&"kill: usage: kill [-s sigspec | -sigspec] pid | jobspec ...")?;
+            return Ok(2);
+        }
+
+        let mut status = 0;
+        for target in args {
+            let sent = if target.starts_with('%') {
+                let job = term
+                    .jobs
+                    .find(Some(target))
+                    .and_then(|id| term.jobs.get_mut(id));
+                let Some(job) = job else {
+                    self.print_err(&format!("kill: {}: no such job", target))?;
+                    status = 1;
+                    continue;
+                };
+                match job.group {
+                    Some(group) => signal::kill(-group, sig).and_then(|_| {
+                        match job.state() == JobState::Stopped && sig != signal::SIGCONT {
+                            true => job.resume(),
+                            false => Ok(()),
+                        }
+                    }),
+                    // A job of builtins alone has finished by the time it is listed.
+                    None => Ok(()),
+                }
+            } else {
+                match target.parse() {
+                    Ok(pid) => signal::kill(pid, sig),
+                    Err(_) => {
+                        self.print_err(&format!(
+                            "kill: {}: arguments must be process or job IDs",
+                            target
+                        ))?;
+                        status = 1;
+                        continue;
+                    }
+                }
+            };
+            if let Err(e) = sent {
+                self.print_err(&format!("kill: ({}) - {}", target, strerror(&e)))?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Lists the signal names, or with arguments, names the signal each number stands for.
+    /// The status of a command killed by a signal, `128` more than its number, names it too.
+    fn list_signals(&mut self, numbers: &[String]) -> Result<i32> {
+        if numbers.is_empty() {
+            let names: Vec<_> = signal::names().iter().map(|(name, _)| *name).collect();
+            self.print_out(&names.join(" "))?;
+            return Ok(0);
+        }
+        let mut status = 0;
+        for number in numbers {
+            let name = number
+                .parse::<i32>()
+                .ok()
+                .and_then(|n| signal::name(if n > 128 { n - 128 } else { n }));
+            match name {
+                Some(name) => self.print_out(&name)?,
+                None => {
+                    self.print_err(&format!("kill: {}: invalid signal specification", number))?;
+                    status = 1;
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    /// Changes directory and pushes the one left onto the stack. Without an argument it
+    /// swaps the current directory with the one on top of the stack instead.
+    fn handle_pushd(&mut self, term: &mut Terminal) -> Result<i32> {
+        let previous = working_dir()?;
+        let target = match self.args.first() {
+            Some(dir) => dir.clone(),
+            None => match term.dir_stack.pop() {
+                Some(dir) => dir.to_string_lossy().into_owned(),
+                None => {
+                    self.print_err(&"pushd: no other directory")?;
+                    return Ok(1);
+                }
+            },
+        };
+        if !self.change_dir("pushd", &target)? {
+            if self.args.is_empty() {
+                term.dir_stack.push(target.into());
+            }
+            return Ok(1);
+        }
+        term.dir_stack.push(previous);
+        self.print_dirs(term)
+    }
+
+    /// Changes to the directory on top of the stack and takes it off.
+    fn handle_popd(&mut self, term: &mut Terminal) -> Result<i32> {
+        let Some(dir) = term.dir_stack.last().cloned() else {
+            self.print_err(&"popd: directory stack empty")?;
+            return Ok(1);
+        };
+        if !self.change_dir("popd", &dir.to_string_lossy())? {
+            return Ok(1);
+        }
+        term.dir_stack.pop();
+        self.print_dirs(term)
+    }
+
+    /// Prints the current directory followed by the stack from the top down.
+    fn print_dirs(&mut self, term: &Terminal) -> Result<i32> {
+        let dirs: Vec<_> = iter::once(working_dir()?)
+            .chain(term.dir_stack.iter().rev().cloned())
+            .map(|dir| prompt::abbreviate_home(&dir.to_string_lossy()))
+            .collect();
+        self.print_out(&dirs.join(" "))?;
+        Ok(0)
+    }
+
+    fn handle_export(&mut self, term: &mut Terminal) -> Result<i32> {
+        if self.args.is_empty() {
+            let mut vars: Vec<_> = env::vars().collect();
+            vars.sort();
+            for (name, value) in vars {
+                self.print_out(&format!("declare -x {}=\"{}\"", name, value))?;
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for arg in self.args.clone() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if !is_name(name) {
+                self.print_err(&format!("export: `{}': not a valid identifier", arg))?;
+                status = 1;
+                continue;
+            }
+            // Exporting a shell variable moves it into the environment.
+            let value = value
+                .map(String::from)
+                .or_else(|| term.variables.remove(name));
+            term.variables.remove(name);
+            if let Some(value) = value {
+                env::set_var(name, value);
+            }
+        }
+        Ok(status)
+    }
+
+    fn handle_unset(&mut self, term: &mut Terminal) -> Result<i32> {
+        let mut status = 0;
+        for name in self.args.clone() {
+            if !is_name(&name) {
+                self.print_err(&format!("unset: `{}': not a valid identifier", name))?;
+                status = 1;
+                continue;
+            }
+            term.variables.remove(&name);
+            env::remove_var(&name);
+        }
+        Ok(status)
+    }
+
+    /// Lists the environment, or runs a command with the leading `NAME=value` arguments added
+    /// to the environment for that command alone.
+    fn handle_env(&mut self) -> Result<i32> {
+        let count = self
+            .args
+            .iter()
+            .take_while(|arg| {
+                arg.split_once('=')
+                    .is_some_and(|(name, _)| !name.is_empty())
+            })
+            .count();
+        let assigned: Vec<_> = self.args[..count]
+            .iter()
+            .filter_map(|arg| arg.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        let Some(name) = self.args.get(count).cloned() else {
+            let mut vars: Vec<_> = env::vars()
+                .filter(|(name, _)| assigned.iter().all(|(assigned, _)| assigned != name))
+                .chain(assigned.clone())
+                .collect();
+            vars.sort();
+            for (name, value) in vars {
+                self.print_out(&format!("{}={}", name, value))?;
+            }
+            return Ok(0);
+        };
+        let mut command = Command {
+            name,
+            args: self.args[count + 1..].to_vec(),
+            input: mem::replace(&mut self.input, Input::Stdin),
+            out: self.out.try_clone()?,
+            err: self.err.try_clone()?,
+            env: assigned,
+        };
+        match command.spawn(None, false)? {
+            Ok(mut child) => Ok(exit_code(child.wait()?)),
+            Err(status) => Ok(status),
+        }
+    }
+
+    /// Reads a line of input and splits it on `IFS` into the named variables, the last of
+    /// which takes whatever is left over. Unless `-r` is given, a backslash keeps the next
+    /// character from splitting, and one at the end of the line joins it with the next.
+    fn handle_read(&mut self, term: &mut Terminal) -> Result<i32> {
+        let (raw, names) = match self.args.first().map(String::as_str) {
+            Some("-r") => (true, self.args[1..].to_vec()),
+            _ => (false, self.args.clone()),
+        };
+        if let Some(name) = names.iter().find(|name| !is_name(name)) {
+            self.print_err(&format!("read: `{}': not a valid identifier", name))?;
+            return Ok(1);
+        }
+
+        let (line, complete) = read_line(&mut self.input, raw)?;
+        if names.is_empty() {
+            term.set_variable("REPLY", &line.iter().map(|&(c, _)| c).collect::<String>());
         } else {
-            self.print_err(&format!("{}: command not found", self.name))
+            let ifs = term.lookup("IFS").unwrap_or_else(|| " \t\n".to_string());
+            let fields = split_fields(&line, &ifs, names.len());
+            for (i, name) in names.iter().enumerate() {
+                term.set_variable(name, fields.get(i).map_or("", String::as_str));
+            }
         }
+        // Input that ends before a newline still fills in the variables, but counts as
+        // running out.
+        Ok(if complete { 0 } else { 1 })
+    }
+
+    /// Runs a file in the current shell, so that the variables and aliases it defines stay
+    /// around.
+    fn handle_source(&mut self, term: &mut Terminal) -> Result<i32> {
+        let Some(path) = self.args.first().cloned() else {
+            self.print_err(&format!("{}: filename argument required", self.name))?;
+            return Ok(2);
+        };
+        let script = match fs::read_to_string(&path) {
+            Ok(script) => script,
+            Err(e) => {
+                self.print_err(&format!("{}: {}", path, strerror(&e)))?;
+                return Ok(1);
+            }
+        };
+
+        term.source(&path, &script, &mut self.err)
     }
 
     fn print_out(&mut self, msg: &dyn Display) -> Result<()> {
@@ -91,20 +1363,510 @@ impl Command {
     }
 }
 
-fn find_command_path(cmd: &str) -> Option<PathBuf> {
-    env::var("PATH").ok().and_then(|paths| {
-        env::split_paths(&paths).find_map(|path| {
-            let full = path.join(cmd);
-            if full.is_file() {
-                Some(full)
-            } else {
-                None
+/// Reads a line for `read` one byte at a time, so that nothing after it is taken from the
+/// input. Each character comes with whether a backslash escaped it, and the line comes with
+/// whether it ended in a newline rather than the end of the input.
+fn read_line(input: &mut Input, raw: bool) -> io::Result<(Vec<(char, bool)>, bool)> {
+    let mut bytes = vec![];
+    let complete = loop {
+        let mut byte = [0];
+        match input.read(&mut byte) {
+            Ok(0) => break false,
+            Ok(_) if byte[0] == b'\n' => {
+                let backslashes = bytes.iter().rev().take_while(|&&b| b == b'\\').count();
+                if raw || backslashes % 2 == 0 {
+                    break true;
+                }
+                bytes.pop();
             }
-        })
-    })
+            Ok(_) => bytes.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    };
+
+    let text = String::from_utf8_lossy(&bytes);
+    let mut line = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !raw => line.extend(chars.next().map(|c| (c, true))),
+            c => line.push((c, false)),
+        }
+    }
+    Ok((line, complete))
+}
+
+/// Splits a line into at most `count` fields the way `read` does. Runs of whitespace in
+/// `ifs` separate fields and are trimmed from the ends, while any other character in `ifs`
+/// separates fields on its own. The last field keeps the rest of the line.
+fn split_fields(line: &[(char, bool)], ifs: &str, count: usize) -> Vec<String> {
+    let is_space = |&(c, escaped): &(char, bool)| !escaped && c.is_whitespace() && ifs.contains(c);
+    let is_delimiter = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+
+    let mut fields = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.iter().position(|c| !is_space(c)) {
+        rest = &rest[start..];
+        if fields.len() + 1 == count {
+            let end = rest.iter().rposition(|c| !is_space(c)).map_or(0, |i| i + 1);
+            fields.push(rest[..end].iter().map(|&(c, _)| c).collect());
+            break;
+        }
+        let end = rest.iter().position(is_delimiter).unwrap_or(rest.len());
+        fields.push(rest[..end].iter().map(|&(c, _)| c).collect());
+        rest = &rest[end..];
+        // A delimiter that isn't whitespace is only skipped once, along with the
+        // whitespace around it.
+        let spaces = rest.iter().take_while(|c| is_space(c)).count();
+        rest = &rest[spaces..];
+        if rest.first().is_some_and(is_delimiter) {
+            rest = &rest[1..];
+        }
+    }
+    fields
 }
 
-fn handle_exit(args: Vec<String>) -> ! {
-    let status = args.get(0).and_then(|s| s.parse().ok()).unwrap_or(0);
-    process::exit(status);
+/// Creates a file of its own in the temporary directory, named after `prefix`. The name is
+/// never one that is already taken, so that a file or link left at a name that was guessed
+/// can't be written through.
+fn create_temp_file(prefix: &str) -> io::Result<(PathBuf, File)> {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let mut error = None;
+    for attempt in 0..100u32 {
+        let suffix = seed ^ attempt.wrapping_mul(0x9e37_79b9);
+        let name = format!("{}-{}-{:08x}.sh", prefix, process::id(), suffix);
+        let path = env::temp_dir().join(name);
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path);
+        match created {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => error = Some(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| io::ErrorKind::AlreadyExists.into()))
+}
+
+/// Finds the directory `cd` should go to for `path` in `cdpath`, the value of `CDPATH`, or
+/// `None` if it doesn't apply: to paths that are absolute, start with `.` or `..`, or name a
+/// directory already.
+fn search_cdpath(path: &str, cdpath: &str) -> Option<String> {
+    let explicit = path.starts_with('/')
+        || matches!(
+            Path::new(path).components().next(),
+            Some(Component::CurDir | Component::ParentDir)
+        );
+    if path.is_empty() || path == "-" || explicit || Path::new(path).is_dir() {
+        return None;
+    }
+    cdpath
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join(path))
+        .find(|dir| dir.is_dir())
+        .map(|dir| dir.to_string_lossy().into_owned())
+}
+
+/// Evaluates a `test` expression, which is told apart by how many words it has, as POSIX
+/// lays out: one is true if it isn't empty, two are a unary operator and its operand, and
+/// three are a binary operator between its operands. A leading `!` negates the rest.
+fn evaluate(args: &[String]) -> std::result::Result<bool, String> {
+    let integer = |arg: &String| {
+        arg.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("{}: integer expression expected", arg))
+    };
+    match args {
+        [] => Ok(false),
+        [arg] => Ok(!arg.is_empty()),
+        [not, rest @ ..] if not == "!" => evaluate(rest).map(|holds| !holds),
+        [op, arg] => {
+            let path = Path::new(arg);
+            match op.as_str() {
+                "-e" => Ok(path.exists()),
+                "-f" => Ok(path.is_file()),
+                "-d" => Ok(path.is_dir()),
+                "-z" => Ok(arg.is_empty()),
+                "-n" => Ok(!arg.is_empty()),
+                _ => Err(format!("{}: unary operator expected", op)),
+            }
+        }
+        [left, op, right] => match op.as_str() {
+            "=" | "==" => Ok(left == right),
+            "!=" => Ok(left != right),
+            "-eq" => Ok(integer(left)? == integer(right)?),
+            "-ne" => Ok(integer(left)? != integer(right)?),
+            "-lt" => Ok(integer(left)? < integer(right)?),
+            "-le" => Ok(integer(left)? <= integer(right)?),
+            "-gt" => Ok(integer(left)? > integer(right)?),
+            "-ge" => Ok(integer(left)? >= integer(right)?),
+            _ => Err(format!("{}: binary operator expected", op)),
+        },
+        _ => Err("too many arguments".to_string()),
+    }
+}
+
+/// Formats `args` the way `printf` does: `%s`, `%d`, `%x`, `%c` and `%%`, each with an
+/// optional `-` or `0` flag and a width, and backslash escapes. The format is used again
+/// for as long as arguments are left, and missing ones count as empty or zero. Returns
+/// the text with a message for each argument that isn't the number it should be.
+fn printf(format: &str, args: &[String]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut errors = vec![];
+    let mut args = args.iter();
+    loop {
+        let left = args.len();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    literal.push(c);
+                    literal.extend(chars.next());
+                    continue;
+                }
+                '%' => {}
+                _ => {
+                    literal.push(c);
+                    continue;
+                }
+            }
+            out.push_str(&unescape(&std::mem::take(&mut literal), false).0);
+
+            let mut flags = String::new();
+            while let Some(flag) = chars.next_if(|c| matches!(c, '-' | '0')) {
+                flags.push(flag);
+            }
+            let mut digits = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+            let width = digits.parse().unwrap_or(0);
+            let left_align = flags.contains('-');
+            let mut number = |radix: fn(i64) -> String| {
+                let arg = args.next().map_or("", |arg| arg.trim());
+                let n = match arg.parse::<i64>() {
+                    Ok(n) => n,
+                    Err(_) if arg.is_empty() => 0,
+                    Err(_) => {
+                        errors.push(format!("{}: invalid number", arg));
+                        0
+                    }
+                };
+                let zeros = flags.contains('0') && !left_align;
+                pad(&radix(n), width, left_align, zeros)
+            };
+            let converted = match chars.next() {
+                Some('%') => "%".to_string(),
+                Some('d' | 'i') => number(|n| n.to_string()),
+                Some('x') => number(|n| format!("{:x}", n)),
+                Some('X') => number(|n| format!("{:X}", n)),
+                Some('s') => pad(args.next().map_or("", |arg| arg), width, left_align, false),
+                Some('c') => {
+                    let c = args.next().and_then(|arg| arg.chars().next());
+                    pad(
+                        &c.map(String::from).unwrap_or_default(),
+                        width,
+                        left_align,
+                        false,
+                    )
+                }
+                Some(other) => format!("%{}{}{}", flags, digits, other),
+                None => "%".to_string(),
+            };
+            out.push_str(&converted);
+        }
+        out.push_str(&unescape(&literal, false).0);
+        // Only a format that takes arguments goes round again.
+        if args.len() == 0 || args.len() == left {
+            return (out, errors);
+        }
+    }
+}
+
+/// Pads `text` with spaces, or zeros after any sign, to at least `width` columns.
+fn pad(text: &str, width: usize, left_align: bool, zeros: bool) -> String {
+    let fill = width.saturating_sub(text.chars().count());
+    match (left_align, zeros) {
+        (true, _) => format!("{}{}", text, " ".repeat(fill)),
+        (false, true) => match text.strip_prefix('-') {
+            Some(digits) => format!("-{}{}", "0".repeat(fill), digits),
+            None => format!("{}{}", "0".repeat(fill), text),
+        },
+        (false, false) => format!("{}{}", " ".repeat(fill), text),
+    }
+}
+
+/// Interprets the backslash escapes `echo -e` and `printf` understand, like `\\t`. The
+/// character with octal code `NNN` is `\\0NNN` for `echo`, which passes `zero_octal`, and
+/// `\\NNN` for `printf`. Returns whether a `\\c`, which only `echo` knows, cut the text short.
+fn unescape(text: &str, zero_octal: bool) -> (String, bool) {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('a') => unescaped.push('\x07'),
+            Some('b') => unescaped.push('\x08'),
+            Some('e') => unescaped.push('\x1b'),
+            Some('f') => unescaped.push('\x0c'),
+            Some('v') => unescaped.push('\x0b'),
+            Some('\\') => unescaped.push('\\'),
+            Some('c') if zero_octal => return (unescaped, true),
+            Some(digit) if digit.is_digit(8) && (!zero_octal || digit == '0') => {
+                let (mut code, more) = match zero_octal {
+                    true => (0, 3),
+                    false => (digit.to_digit(8).unwrap_or(0), 2),
+                };
+                for _ in 0..more {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => code = code * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                unescaped.extend(char::from_u32(code));
+            }
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    (unescaped, false)
+}
+
+/// Puts `word` in single quotes if it is empty or has characters special to the shell.
+fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    single_quote(word)
+}
+
+/// The current directory as reached through `cd`, which `PWD` keeps track of when the way
+/// there went through symlinks.
+pub fn working_dir() -> io::Result<PathBuf> {
+    let cwd = env::current_dir()?;
+    match env::var_os("PWD").map(PathBuf::from) {
+        Some(pwd)
+            if pwd.is_absolute() && fs::canonicalize(&pwd).ok() == fs::canonicalize(&cwd).ok() =>
+        {
+            Ok(pwd)
+        }
+        _ => Ok(cwd),
+    }
+}
+
+/// Resolves the `.` and `..` components of an absolute path without looking at the
+/// filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// The names of the builtins, for completion.
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+    Builtin::iter().map(<&str>::from)
+}
+
+/// Finds the file `cmd` runs: itself if it has a slash in it, or else the first executable
+/// file of that name on `PATH`. Without one, the first file that isn't executable is found
+/// instead, for running it to fail with the reason.
+fn find_command_path(cmd: &str) -> Option<PathBuf> {
+    if cmd.contains('/') {
+        let path = PathBuf::from(cmd);
+        return path.is_file().then_some(path);
+    }
+    let paths = env::var_os("PATH")?;
+    let mut files = env::split_paths(&paths)
+        .map(|dir| dir.join(cmd))
+        .filter(|path| path.is_file());
+    let first = files.next()?;
+    if is_executable(&first) {
+        return Some(first);
+    }
+    Some(files.find(|path| is_executable(path)).unwrap_or(first))
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+/// Describes an I/O error the way shells do, without Rust's `(os error N)` suffix.
+pub fn strerror(e: &io::Error) -> String {
+    let msg = e.to_string();
+    match msg.find(" (os error") {
+        Some(end) => msg[..end].to_string(),
+        None => msg,
+    }
+}
+
+/// Quotes `s` so that the shell reads it back literally.
+fn single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Maps a child's exit status to a shell status, using `128 + signal` for killed children.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|sig| 128 + sig))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits `line`, where a backslash escapes the character after it, like `read` does.
+    fn fields(line: &str, ifs: &str, count: usize) -> Vec<String> {
+        let mut chars = vec![];
+        let mut iter = line.chars();
+        while let Some(c) = iter.next() {
+            match c {
+                '\\' => chars.extend(iter.next().map(|c| (c, true))),
+                c => chars.push((c, false)),
+            }
+        }
+        split_fields(&chars, ifs, count)
+    }
+
+    #[test]
+    fn whitespace_runs_separate_fields() {
+        assert_eq!(fields("  a  b\tc  ", " \t\n", 3), ["a", "b", "c"]);
+        assert_eq!(fields("a b c d", " \t\n", 2), ["a", "b c d"]);
+        assert_eq!(fields("  a b  ", " \t\n", 1), ["a b"]);
+        assert_eq!(fields("a", " \t\n", 3), ["a"]);
+        assert!(fields("   ", " \t\n", 2).is_empty());
+    }
+
+    #[test]
+    fn other_delimiters_separate_on_their_own() {
+        assert_eq!(fields("a:b::c", ":", 4), ["a", "b", "", "c"]);
+        assert_eq!(fields("a : b", " :", 2), ["a", "b"]);
+        assert_eq!(fields("a:b:c", ":", 2), ["a", "b:c"]);
+    }
+
+    #[test]
+    fn escaped_delimiters_stay_in_the_field() {
+        assert_eq!(fields("a\\ b c", " ", 2), ["a b", "c"]);
+        assert_eq!(fields("a\\:b:c", ":", 2), ["a:b", "c"]);
+    }
+
+    fn format(format: &str, args: &[&str]) -> (String, Vec<String>) {
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+        printf(format, &args)
+    }
+
+    #[test]
+    fn printf_conversions() {
+        assert_eq!(format("%s-%s\\n", &["a", "b"]).0, "a-b\n");
+        assert_eq!(
+            format("%d %x %X %c %%", &["42", "255", "255", "xyz"]).0,
+            "42 ff FF x %"
+        );
+        assert_eq!(
+            format("[%5s|%-5s|%05d|%-4d|%03d]", &["a", "b", "42", "7", "-5"]).0,
+            "[    a|b    |00042|7   |-05]"
+        );
+        assert_eq!(format("%q %5q", &[]).0, "%q %5q");
+    }
+
+    #[test]
+    fn printf_reuses_the_format() {
+        assert_eq!(format("<%s>", &["a", "b", "c"]).0, "<a><b><c>");
+        assert_eq!(format("%s=%d ", &["a", "1", "b"]).0, "a=1 b=0 ");
+        assert_eq!(format("plain\\n", &["ignored"]).0, "plain\n");
+    }
+
+    #[test]
+    fn printf_missing_and_invalid_arguments() {
+        assert_eq!(format("[%s][%d]", &[]), ("[][0]".to_string(), vec![]));
+        assert_eq!(
+            format("%d", &["x1"]),
+            ("0".to_string(), vec!["x1: invalid number".to_string()])
+        );
+    }
+
+    #[test]
+    fn every_builtin_has_help_under_its_name() {
+        for builtin in Builtin::iter() {
+            let name: &str = (&builtin).into();
+            let (usage, description) = builtin.help();
+            assert!(usage.starts_with(name), "{:?} for {}", usage, name);
+            assert!(!description.is_empty(), "no description for {}", name);
+            assert!(Builtin::try_from(name).is_ok());
+        }
+    }
+
+    fn holds(args: &[&str]) -> std::result::Result<bool, String> {
+        evaluate(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(holds(&["-z", ""]), Ok(true));
+        assert_eq!(holds(&["-z", "a"]), Ok(false));
+        assert_eq!(holds(&["-n", "a"]), Ok(true));
+        assert_eq!(holds(&["a"]), Ok(true));
+        assert_eq!(holds(&[""]), Ok(false));
+        assert_eq!(holds(&[]), Ok(false));
+        assert_eq!(holds(&["a", "=", "a"]), Ok(true));
+        assert_eq!(holds(&["a", "!=", "a"]), Ok(false));
+        assert_eq!(holds(&["!", "-z", ""]), Ok(false));
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(holds(&["2", "-lt", "10"]), Ok(true));
+        assert_eq!(holds(&["10", "-gt", "2"]), Ok(true));
+        assert_eq!(holds(&["-3", "-eq", " -3"]), Ok(true));
+        assert_eq!(holds(&["3", "-ne", "3"]), Ok(false));
+        assert_eq!(holds(&["3", "-le", "3"]), Ok(true));
+        assert_eq!(holds(&["2", "-ge", "3"]), Ok(false));
+        assert_eq!(
+            holds(&["3", "-lt", "x"]),
+            Err("x: integer expression expected".to_string())
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            holds(&["-q", "a"]),
+            Err("-q: unary operator expected".to_string())
+        );
+        assert_eq!(
+            holds(&["a", "-q", "b"]),
+            Err("-q: binary operator expected".to_string())
+        );
+        assert_eq!(
+            holds(&["a", "b", "c", "d"]),
+            Err("too many arguments".to_string())
+        );
+    }
 }
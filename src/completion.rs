@@ -0,0 +1,492 @@
+use std::{env, ffi::OsString, fs, os::unix::fs::PermissionsExt};
+
+use crate::{command::builtin_names, tty};
+
+/// How many candidates can be listed without asking first when `COMPLETION_QUERY_ITEMS`
+/// isn't set to a number.
+const DEFAULT_QUERY_ITEMS: usize = 100;
+
+/// The ways the word at the end of a line could be completed.
+pub struct Completion {
+    /// The byte offset the word starts at.
+    pub start: usize,
+    /// The candidates with their quoting removed, sorted. Directories end with `/`; every
+    /// other candidate is a complete word.
+    pub candidates: Vec<String>,
+    /// The quote the word is still inside of, if any.
+    quote: Option<char>,
+}
+
+impl Completion {
+    /// Quotes `text` the way the word being completed is quoted, so that the line still
+    /// reads back as `text`. A `finished` word gets its open quote closed.
+    pub fn quote(&self, text: &str, finished: bool) -> String {
+        let mut quoted = String::new();
+        match self.quote {
+            None => {
+                for c in text.chars() {
+                    if c.is_whitespace() || "'\"\\$`!*?[]{}()<>|;&#~".contains(c) {
+                        quoted.push('\\');
+                    }
+                    quoted.push(c);
+                }
+            }
+            Some(quote) => {
+                quoted.push(quote);
+                for c in text.chars() {
+                    match (quote, c) {
+                        ('"', '$' | '`' | '"' | '\\') => quoted.push('\\'),
+                        ('\'', '\'') => quoted.push_str("'\\'"),
+                        _ => {}
+                    }
+                    quoted.push(c);
+                }
+                if finished {
+                    quoted.push(quote);
+                }
+            }
+        }
+        quoted
+    }
+}
+
+/// The names of the executables on `PATH`, read once and kept until `PATH` changes or
+/// `rehash` asks for them to be read again.
+#[derive(Default)]
+pub struct Executables {
+    /// The `PATH` the names were found on, or `None` if they haven't been looked for yet.
+    path: Option<OsString>,
+    names: Vec<String>,
+}
+
+impl Executables {
+    /// Forgets the names found, so that the next completion reads `PATH` again.
+    pub fn clear(&mut self) {
+        self.path = None;
+        self.names.clear();
+    }
+
+    /// The names of the executables on the current `PATH`, sorted and without duplicates.
+    fn names(&mut self) -> &[String] {
+        self.names_on(env::var_os("PATH").unwrap_or_default())
+    }
+
+    /// The names of the executables on `path`, which is only read again if it isn't the
+    /// one read last.
+    fn names_on(&mut self, path: OsString) -> &[String] {
+        if self.path.as_ref() != Some(&path) {
+            self.names = scan(&path);
+            self.path = Some(path);
+        }
+        &self.names
+    }
+}
+
+/// Reads every directory on `path` for the files that can be run.
+fn scan(path: &OsString) -> Vec<String> {
+    let mut names = vec![];
+    for dir in env::split_paths(path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            // Symlinks count by what they point to.
+            let executable = fs::metadata(entry.path())
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+            if executable {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Finds the ways the word ending at the end of `line` could be completed. The first word of
+/// a command completes to a builtin, alias or executable on `PATH` unless it looks like a
+/// path, and every other word completes to a file.
+pub fn complete<'a>(
+    line: &str,
+    aliases: impl Iterator<Item = &'a String>,
+    executables: &mut Executables,
+) -> Completion {
+    let (start, word, quote) = last_word(line);
+    let before = line[..start].trim_end();
+    let command_position = before.is_empty() || before.ends_with(is_operator);
+
+    let mut candidates = if command_position && !word.contains('/') {
+        commands(&word, aliases, executables)
+    } else {
+        paths(&word)
+    };
+    candidates.sort();
+    candidates.dedup();
+    Completion {
+        start,
+        candidates,
+        quote,
+    }
+}
+
+/// Finds where the last word of `line` starts, what it says with its quoting removed, and
+/// the quote it is still inside of, if any.
+fn last_word(line: &str) -> (usize, String, Option<char>) {
+    let mut start = 0;
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                match chars.next_if(|(_, c)| matches!(c, '$' | '`' | '"' | '\\')) {
+                    Some((_, escaped)) => word.push(escaped),
+                    None => word.push('\\'),
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => word.extend(chars.next().map(|(_, c)| c)),
+            (None, c) if c.is_whitespace() || is_operator(c) => {
+                start = i + c.len_utf8();
+                word.clear();
+            }
+            (None, c) => word.push(c),
+        }
+    }
+    (start, word, quote)
+}
+
+/// The longest prefix every candidate shares.
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let Some(first) = candidates.first() else {
+        return "";
+    };
+    candidates.iter().fold(first, |prefix, candidate| {
+        let len = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(candidate.len()), |((i, _), _)| i);
+        &prefix[..len]
+    })
+}
+
+/// Whether to ask before listing `count` candidates, which is when there are more than
+/// `limit`, the value of `COMPLETION_QUERY_ITEMS`, of them.
+pub fn should_ask(count: usize, limit: Option<&str>) -> bool {
+    let limit = limit
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(DEFAULT_QUERY_ITEMS);
+    count > limit
+}
+
+/// Lays `names` out in columns down then across, as many as fit in `width` terminal columns,
+/// and returns the rows. Each column is as wide as the longest name plus two spaces.
+pub fn layout(names: &[&str], width: usize) -> Vec<String> {
+    let column_width = names.iter().map(|name| tty::width(name)).max().unwrap_or(0) + 2;
+    let columns = (width / column_width).max(1);
+    let rows = names.len().div_ceil(columns);
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            for name in names.iter().skip(row).step_by(rows) {
+                line.push_str(name);
+                line.push_str(&" ".repeat(column_width - tty::width(name)));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+fn is_operator(c: char) -> bool {
+    matches!(c, '|' | ';' | '&' | '<' | '>')
+}
+
+/// The builtins, aliases and executables on `PATH` whose names start with `prefix`. Builtins
+/// run whatever case their name is typed in, so they match in any case but are offered in
+/// the lowercase they are listed in.
+fn commands<'a>(
+    prefix: &str,
+    aliases: impl Iterator<Item = &'a String>,
+    executables: &mut Executables,
+) -> Vec<String> {
+    let lowercase = prefix.to_ascii_lowercase();
+    let mut names: Vec<String> = builtin_names()
+        .filter(|name| name.starts_with(&lowercase))
+        .map(String::from)
+        .chain(aliases.filter(|name| name.starts_with(prefix)).cloned())
+        .collect();
+    let found = executables.names();
+    let first = found.partition_point(|name| name.as_str() < prefix);
+    names.extend(
+        found[first..]
+            .iter()
+            .take_while(|name| name.starts_with(prefix))
+            .cloned(),
+    );
+    names
+}
+
+/// The entries of the directory named in `word` up to its last `/`, or of the current
+/// directory, whose names start with the rest of it.
+fn paths(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files are only offered once a dot has been typed.
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, iter, ops::Deref, path::PathBuf, process};
+
+    use anyhow::{bail, Result};
+
+    use super::*;
+    use crate::token::{tokenize, Expand, Token};
+
+    struct NoExpansion;
+
+    impl Expand for NoExpansion {
+        fn lookup(&self, _: &str) -> Option<String> {
+            None
+        }
+
+        fn substitute(&mut self, _: &str) -> Result<String> {
+            bail!("no substitution here")
+        }
+    }
+
+    /// A directory made for a test, removed with everything in it once the test is done.
+    struct TempDir(PathBuf);
+
+    impl Deref for TempDir {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A directory of its own for a test, holding `files` and `dirs`.
+    fn dir(name: &str, files: &[&str], dirs: &[&str]) -> TempDir {
+        let dir = env::temp_dir().join(format!("completion-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        for sub in dirs {
+            fs::create_dir(dir.join(sub)).unwrap();
+        }
+        TempDir(dir)
+    }
+
+    fn candidates(line: &str) -> Vec<String> {
+        complete(line, iter::empty(), &mut Executables::default()).candidates
+    }
+
+    #[test]
+    fn arguments_complete_to_paths() {
+        let dir = dir(
+            "paths",
+            &["main.rs", "mod.rs", "lib.rs", ".mine"],
+            &["mods"],
+        );
+        let d = dir.display();
+        assert_eq!(
+            candidates(&format!("cat {}/m", d)),
+            [
+                format!("{}/main.rs", d),
+                format!("{}/mod.rs", d),
+                format!("{}/mods/", d)
+            ]
+        );
+        assert_eq!(
+            candidates(&format!("cat {}/l", d)),
+            [format!("{}/lib.rs", d)]
+        );
+        assert_eq!(
+            candidates(&format!("cat {}/.m", d)),
+            [format!("{}/.mine", d)]
+        );
+        assert!(candidates(&format!("cat {}/x", d)).is_empty());
+    }
+
+    #[test]
+    fn command_names_complete_to_commands() {
+        let dir = dir("commands", &["echoes"], &[]);
+        assert!(candidates("ech").contains(&"echo".to_string()));
+        assert!(candidates("ls | ech").contains(&"echo".to_string()));
+        assert_eq!(
+            candidates(&format!("cat {}/ech", dir.display())),
+            [format!("{}/echoes", dir.display())]
+        );
+    }
+
+    #[test]
+    fn later_words_are_arguments() {
+        assert!(!candidates("echo ech").contains(&"echo".to_string()));
+        assert!(!candidates("ls ech").contains(&"echo".to_string()));
+        assert!(candidates("ls && ech").contains(&"echo".to_string()));
+        let completion = complete("cat 'my fi", iter::empty(), &mut Executables::default());
+        assert_eq!((completion.start, completion.quote), (4, Some('\'')));
+    }
+
+    /// What the line reads back as once the word being completed in it is replaced with
+    /// `text`.
+    fn completed(line: &str, text: &str) -> Vec<Token> {
+        let completion = complete(line, iter::empty(), &mut Executables::default());
+        let line = format!(
+            "{}{}",
+            &line[..completion.start],
+            completion.quote(text, true)
+        );
+        tokenize(&line, &mut NoExpansion, &mut VecDeque::new())
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn completions_are_quoted_like_the_word() {
+        let dir = dir("quoted", &["my file.txt"], &[]);
+        let path = format!("{}/my file.txt", dir.display());
+        let prefix = format!("{}/my", dir.display());
+        for line in [
+            format!("cat {}", prefix.replace(' ', "\\ ")),
+            format!("cat '{}", prefix),
+            format!("cat \"{}", prefix),
+        ] {
+            let completion = complete(&line, iter::empty(), &mut Executables::default());
+            assert_eq!(completion.candidates, std::slice::from_ref(&path));
+            assert_eq!(
+                completed(&line, &path),
+                [Token::Arg("cat".into()), Token::Arg(path.clone())]
+            );
+        }
+    }
+
+    #[test]
+    fn special_characters_survive_quoting() {
+        for text in ["a b", "it's", "$HOME", "a\"b\\c", "*?[x]", "a;b|c&d"] {
+            for line in ["cat x", "cat 'x", "cat \"x"] {
+                assert_eq!(
+                    completed(line, text),
+                    [Token::Arg("cat".into()), Token::Arg(text.into())],
+                    "{:?} completing {:?}",
+                    text,
+                    line
+                );
+            }
+        }
+    }
+
+    fn executable(path: PathBuf) {
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn executables_are_cached_until_path_changes() {
+        let one = dir("cache-one", &[], &[]);
+        let two = dir("cache-two", &[], &[]);
+        executable(one.join("alpha"));
+        executable(two.join("beta"));
+        executable(two.join("alpha"));
+        let path = env::join_paths([&*one, &*two]).unwrap();
+
+        let mut executables = Executables::default();
+        assert_eq!(executables.names_on(path.clone()), scan(&path));
+        assert_eq!(executables.names_on(path.clone()), ["alpha", "beta"]);
+        executable(one.join("gamma"));
+        assert_eq!(executables.names_on(path.clone()), ["alpha", "beta"]);
+        assert_eq!(executables.names_on(one.clone().into()), ["alpha", "gamma"]);
+        executable(one.join("delta"));
+        executables.clear();
+        assert_eq!(
+            executables.names_on(one.clone().into()),
+            ["alpha", "delta", "gamma"]
+        );
+    }
+
+    #[test]
+    fn only_executable_files_are_commands() {
+        let bin = dir("executables-only", &["myplain"], &["mydir"]);
+        executable(bin.join("myscript"));
+        fs::set_permissions(bin.join("mydir"), fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(scan(&bin.clone().into()), ["myscript"]);
+    }
+
+    #[test]
+    fn common_prefixes() {
+        let strings = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            common_prefix(&strings(&["main.rs", "mod.rs", "mods/"])),
+            "m"
+        );
+        assert_eq!(common_prefix(&strings(&["echo", "echoes"])), "echo");
+        assert_eq!(common_prefix(&strings(&["only"])), "only");
+        assert_eq!(common_prefix(&strings(&["abc", "xyz"])), "");
+        assert_eq!(common_prefix(&strings(&["日本語", "日本"])), "日本");
+        assert_eq!(common_prefix(&strings(&["éa", "éb"])), "é");
+        assert_eq!(common_prefix(&[]), "");
+    }
+
+    #[test]
+    fn layout_fills_columns_down_then_across() {
+        let names = ["a", "bb", "ccc", "dd", "e"];
+        // Each column is five wide, so three fit in fifteen.
+        assert_eq!(layout(&names, 15), ["a    ccc  e", "bb   dd"]);
+        assert_eq!(layout(&names, 25), ["a    bb   ccc  dd   e"]);
+        assert_eq!(layout(&names, 10), ["a    dd", "bb   e", "ccc"]);
+    }
+
+    #[test]
+    fn layout_keeps_one_column_when_names_are_wide() {
+        assert_eq!(layout(&["long-name", "x"], 5), ["long-name", "x"]);
+        assert_eq!(layout(&["日本", "ab"], 12), ["日本  ab"]);
+        assert!(layout(&[], 80).is_empty());
+    }
+
+    #[test]
+    fn asking_before_long_listings() {
+        assert!(!should_ask(100, None));
+        assert!(should_ask(101, None));
+        assert!(!should_ask(3, Some("3")));
+        assert!(should_ask(4, Some("3")));
+        assert!(should_ask(1, Some("0")));
+        assert!(!should_ask(100, Some("many")));
+        assert!(should_ask(101, Some("")));
+    }
+}
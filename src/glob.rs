@@ -0,0 +1,187 @@
+use std::fs;
+
+/// Expands a glob pattern against the filesystem, returning the matching paths sorted.
+/// `*`, `?` and `[...]` are wildcards unless escaped with a backslash.
+pub fn expand(pattern: &str) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<_> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        paths = if component.is_empty() {
+            // A trailing or doubled slash only keeps directories.
+            paths
+                .into_iter()
+                .filter(|path| fs::metadata(dir_or_cwd(path)).is_ok_and(|m| m.is_dir()))
+                .map(|path| if last { path + "/" } else { path })
+                .collect()
+        } else if has_wildcard(component) {
+            paths
+                .iter()
+                .flat_map(|path| matching_entries(path, component))
+                .collect()
+        } else {
+            let name = unescape(component);
+            paths
+                .into_iter()
+                .map(|path| join(&path, &name))
+                .filter(|path| fs::symlink_metadata(path).is_ok())
+                .collect()
+        };
+        if paths.is_empty() {
+            break;
+        }
+    }
+
+    paths.sort();
+    paths
+}
+
+fn matching_entries(dir: &str, component: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir_or_cwd(dir)) else {
+        return vec![];
+    };
+    let pattern: Vec<_> = component.chars().collect();
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        // Hidden files only match a pattern that starts with a literal dot.
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| matches(&pattern, &name.chars().collect::<Vec<_>>()))
+        .map(|name| join(dir, &name))
+        .collect()
+}
+
+fn dir_or_cwd(path: &str) -> &str {
+    if path.is_empty() {
+        "."
+    } else {
+        path
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn has_wildcard(component: &str) -> bool {
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Removes the backslashes escaping characters in a pattern.
+pub fn unescape(component: &str) -> String {
+    let mut name = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => name.extend(chars.next()),
+            c => name.push(c),
+        }
+    }
+    name
+}
+
+/// Matches a single path component against a pattern.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| matches(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+        Some('[') => match bracket(&pattern[1..], name.first()) {
+            Some((true, len)) => matches(&pattern[1 + len..], &name[1..]),
+            Some((false, _)) => false,
+            // An unclosed bracket is just a literal `[`.
+            None => name.first() == Some(&'[') && matches(&pattern[1..], &name[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && matches(&pattern[2..], &name[1..])
+        }
+        Some(c) => name.first() == Some(c) && matches(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Evaluates a bracket expression following `[`, returning whether `c` is in it and how many
+/// pattern characters it spans including the closing `]`. Returns `None` if it isn't closed.
+fn bracket(pattern: &[char], c: Option<&char>) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let mut i = usize::from(negated);
+    let mut found = false;
+    let mut first = true;
+
+    while i < pattern.len() {
+        // A `]` right after the opening bracket is part of the set.
+        if pattern[i] == ']' && !first {
+            return Some((found != negated, i + 1));
+        }
+        first = false;
+        let lo = pattern[i];
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let hi = pattern[i + 2];
+            found |= c.is_some_and(|&c| lo <= c && c <= hi);
+            i += 3;
+        } else {
+            found |= c == Some(&lo);
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<_> = pattern.chars().collect();
+        matches(&pattern, &name.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(is_match("*.txt", "a.txt"));
+        assert!(is_match("*.txt", ".txt"));
+        assert!(!is_match("*.txt", "a.txt.bak"));
+        assert!(is_match("file?.log", "file1.log"));
+        assert!(!is_match("file?.log", "file.log"));
+        assert!(is_match("a*b*c", "aXbYbc"));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(is_match("[abc]*", "beta"));
+        assert!(!is_match("[abc]*", "delta"));
+        assert!(is_match("[!abc]*", "delta"));
+        assert!(is_match("[^abc]*", "delta"));
+        assert!(is_match("x[0-9]", "x7"));
+        assert!(!is_match("x[0-9]", "xa"));
+        assert!(is_match("[]a]", "]"));
+        assert!(is_match("[a-]", "-"));
+        assert!(is_match("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(is_match("\\*", "*"));
+        assert!(!is_match("\\*", "a"));
+        assert!(!has_wildcard("a\\*b"));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+    }
+}
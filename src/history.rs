@@ -0,0 +1,453 @@
+use anyhow::{bail, Result};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// How many entries are kept when `HISTSIZE` isn't set to a number.
+const DEFAULT_SIZE: usize = 500;
+
+/// A line with its history references expanded.
+pub struct Expansion {
+    pub line: String,
+    /// Set by a `:p` modifier, which asks for the line to be shown and recorded but not run.
+    pub print_only: bool,
+}
+
+/// The commands entered during the session, oldest first.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// The file the history is loaded from and saved back to, if any.
+    path: Option<PathBuf>,
+    /// How many of the entries, from the oldest, are already in a file, which `history -a`
+    /// leaves out.
+    saved: usize,
+}
+
+impl History {
+    /// Loads the history kept in `path`, keeping the latest `size` entries. A missing file
+    /// just starts an empty history.
+    pub fn open(path: impl Into<PathBuf>, size: usize) -> io::Result<Self> {
+        let path = path.into();
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(String::from).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let mut history = History {
+            saved: entries.len(),
+            entries,
+            path: Some(path),
+        };
+        history.evict(size);
+        Ok(history)
+    }
+
+    /// The file the history was loaded from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Writes the entries back to the file they were loaded from.
+    pub fn save(&mut self) -> io::Result<()> {
+        match self.path.clone() {
+            Some(path) => self.write(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the contents of `path` with the entries.
+    pub fn write(&mut self, path: &Path) -> io::Result<()> {
+        fs::write(path, lines(&self.entries))?;
+        self.saved = self.entries.len();
+        Ok(())
+    }
+
+    /// Adds the entries not yet in a file to the end of `path`.
+    pub fn append(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(lines(&self.entries[self.saved..]).as_bytes())?;
+        self.saved = self.entries.len();
+        Ok(())
+    }
+
+    /// Adds the lines of `path` to the end of the history. They count as saved unless
+    /// something entered before them still isn't.
+    pub fn read(&mut self, path: &Path, size: usize) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        let all_saved = self.saved == self.entries.len();
+        self.entries.extend(contents.lines().map(String::from));
+        if all_saved {
+            self.saved = self.entries.len();
+        }
+        self.evict(size);
+        Ok(())
+    }
+
+    /// Records a command, skipping blank lines, and then keeps only the latest `size`
+    /// entries. `control`, the value of `HISTCONTROL`, is a colon-separated list of what else
+    /// to leave out: `ignorespace` skips commands starting with a space, `ignoredups` skips
+    /// repeats of the previous entry, `ignoreboth` does both, and `erasedups` removes earlier
+    /// copies of the command instead.
+    pub fn add(&mut self, command: &str, control: &str, size: usize) {
+        let command = command.trim_end_matches('\n');
+        if command.trim().is_empty() {
+            return;
+        }
+        let options: Vec<_> = control.split(':').collect();
+        let ignore_space = options
+            .iter()
+            .any(|o| matches!(*o, "ignorespace" | "ignoreboth"));
+        let ignore_dups = options
+            .iter()
+            .any(|o| matches!(*o, "ignoredups" | "ignoreboth"));
+        if ignore_space && command.starts_with(' ') {
+            return;
+        }
+        if ignore_dups && self.entries.last().is_some_and(|last| last == command) {
+            return;
+        }
+        if options.contains(&"erasedups") {
+            let erased_saved = self.entries[..self.saved]
+                .iter()
+                .filter(|entry| *entry == command)
+                .count();
+            self.saved -= erased_saved;
+            self.entries.retain(|entry| entry != command);
+        }
+        self.entries.push(command.to_string());
+        self.evict(size);
+    }
+
+    /// Drops the oldest entries beyond `size`.
+    fn evict(&mut self, size: usize) {
+        let excess = self.entries.len().saturating_sub(size);
+        self.entries.drain(..excess);
+        self.saved = self.saved.saturating_sub(excess);
+    }
+
+    /// Writes the entries numbered from 1, limited to the most recent `limit` if given.
+    pub fn print(&self, out: &mut dyn Write, limit: Option<usize>) -> io::Result<()> {
+        let skip = limit.map_or(0, |limit| self.entries.len().saturating_sub(limit));
+        for (i, entry) in self.entries.iter().enumerate().skip(skip) {
+            writeln!(out, "{:>5}  {}", i + 1, entry)?;
+        }
+        Ok(())
+    }
+
+    /// Expands history references in `line` like bash: `!!` is the previous command, `!n`
+    /// entry `n`, `!-n` the command `n` back, and `!prefix` the latest command starting
+    /// with `prefix`. A reference followed by `:p` expands as usual, but the line is only
+    /// printed. Nothing is expanded inside single quotes or after a backslash.
+    /// Returns `None` if the line has no references.
+    pub fn expand(&self, line: &str) -> Result<Option<Expansion>> {
+        let mut expanded = String::new();
+        let mut found = false;
+        let mut print_only = false;
+        let mut single_quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => single_quoted = !single_quoted,
+                '\\' if !single_quoted => {
+                    expanded.push(c);
+                    expanded.extend(chars.next());
+                    continue;
+                }
+                '!' if !single_quoted => {
+                    let mut spec = String::new();
+                    match chars.peek() {
+                        Some('!') => spec.push(chars.next().unwrap_or('!')),
+                        Some(&c) if c == '-' || c.is_ascii_digit() => {
+                            spec.push(c);
+                            chars.next();
+                            while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                                spec.push(c);
+                            }
+                        }
+                        Some(&c) if !c.is_whitespace() && !"=(\"".contains(c) => {
+                            while let Some(c) =
+                                chars.next_if(|c| !c.is_whitespace() && !";&|<>()\"':".contains(*c))
+                            {
+                                spec.push(c);
+                            }
+                        }
+                        _ => {}
+                    }
+                    if !spec.is_empty() {
+                        match self.event(&spec) {
+                            Some(entry) => expanded.push_str(entry),
+                            None => bail!("!{}: event not found", spec),
+                        }
+                        let mut modifier = chars.clone();
+                        if modifier.next() == Some(':') && modifier.next() == Some('p') {
+                            chars = modifier;
+                            print_only = true;
+                        }
+                        found = true;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            expanded.push(c);
+        }
+        Ok(found.then_some(Expansion {
+            line: expanded,
+            print_only,
+        }))
+    }
+
+    /// Looks up the entry a history reference like `!` or `-2` refers to, without the `!`.
+    fn event(&self, spec: &str) -> Option<&str> {
+        self.get(self.find(spec)?)
+    }
+
+    /// Finds the index of the entry `spec` refers to: `!` for the last one, a number counting
+    /// from 1, a negative one counting back from the end, or else the prefix of the latest
+    /// entry starting with it.
+    pub fn find(&self, spec: &str) -> Option<usize> {
+        let index = if spec == "!" {
+            self.entries.len().checked_sub(1)?
+        } else if let Ok(n) = spec.parse::<isize>() {
+            match n {
+                n if n < 0 => self.entries.len().checked_sub(n.unsigned_abs())?,
+                n => usize::try_from(n).ok()?.checked_sub(1)?,
+            }
+        } else {
+            return self
+                .entries
+                .iter()
+                .rposition(|entry| entry.starts_with(spec));
+        };
+        (index < self.entries.len()).then_some(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Finds the most recent entry before `before` that contains `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    /// Removes the latest entry, as `fc` does with itself.
+    pub fn pop(&mut self) -> Option<String> {
+        let entry = self.entries.pop();
+        self.saved = self.saved.min(self.entries.len());
+        entry
+    }
+
+    /// Finds the oldest entry from `from` on that contains `query`.
+    pub fn search_forward(&self, query: &str, from: usize) -> Option<usize> {
+        let start = from.min(self.entries.len());
+        self.entries[start..]
+            .iter()
+            .position(|entry| entry.contains(query))
+            .map(|i| start + i)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.saved = 0;
+    }
+}
+
+/// How many entries to keep for `HISTSIZE` set to `value`. `0` turns the history off.
+pub fn size(value: Option<&str>) -> usize {
+    value
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_SIZE)
+}
+
+/// Joins `entries` into the contents of a history file, one to a line.
+fn lines(entries: &[String]) -> String {
+    let mut contents = String::new();
+    for entry in entries {
+        contents.push_str(entry);
+        contents.push('\n');
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(commands: &[&str], control: &str) -> Vec<String> {
+        let mut history = History::default();
+        for command in commands {
+            history.add(command, control, DEFAULT_SIZE);
+        }
+        history.entries
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted() {
+        let mut history = History::default();
+        for command in ["a", "b", "c", "d"] {
+            history.add(command, "", 3);
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.entries, ["b", "c", "d"]);
+        history.add("e", "", 0);
+        assert_eq!(history.len(), 0);
+    }
+
+    #[test]
+    fn size_defaults_unless_a_number() {
+        assert_eq!(size(Some("10")), 10);
+        assert_eq!(size(Some("0")), 0);
+        assert_eq!(size(Some("lots")), DEFAULT_SIZE);
+        assert_eq!(size(None), DEFAULT_SIZE);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        assert_eq!(history(&["a", "", "  ", "b\n"], ""), ["a", "b"]);
+    }
+
+    #[test]
+    fn ignorespace() {
+        assert_eq!(history(&[" a", "b"], "ignorespace"), ["b"]);
+        assert_eq!(history(&[" a", "b"], ""), [" a", "b"]);
+    }
+
+    #[test]
+    fn ignoredups() {
+        assert_eq!(
+            history(&["a", "a", "b", "a"], "ignoredups"),
+            ["a", "b", "a"]
+        );
+        assert_eq!(history(&["a", "a"], ""), ["a", "a"]);
+    }
+
+    #[test]
+    fn ignoreboth() {
+        assert_eq!(history(&["a", "a", " b"], "ignoreboth"), ["a"]);
+        assert_eq!(history(&["a", " b"], "ignoredups:ignorespace"), ["a"]);
+    }
+
+    #[test]
+    fn erasedups() {
+        assert_eq!(history(&["a", "b", "a"], "erasedups"), ["b", "a"]);
+        assert_eq!(
+            history(&["a", "b", "a", "c", "b", "a"], "erasedups"),
+            ["c", "b", "a"]
+        );
+        assert_eq!(history(&[" a", "a"], "erasedups:ignorespace"), ["a"]);
+    }
+
+    /// What `line` expands to after `commands`, or the error expanding it gives.
+    fn expand(commands: &[&str], line: &str) -> Result<Option<String>, String> {
+        let mut history = History::default();
+        for command in commands {
+            history.add(command, "", DEFAULT_SIZE);
+        }
+        match history.expand(line) {
+            Ok(expansion) => Ok(expansion.map(|expansion| expansion.line)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    #[test]
+    fn bang_bang_is_the_previous_command() {
+        let commands = ["echo one", "echo two"];
+        assert_eq!(expand(&commands, "!!"), Ok(Some("echo two".into())));
+        assert_eq!(
+            expand(&commands, "!! | cat"),
+            Ok(Some("echo two | cat".into()))
+        );
+        assert_eq!(expand(&[], "!!"), Err("!!: event not found".into()));
+    }
+
+    #[test]
+    fn numbered_events() {
+        let commands = ["echo one", "echo two", "echo three"];
+        assert_eq!(expand(&commands, "!1"), Ok(Some("echo one".into())));
+        assert_eq!(expand(&commands, "!-2"), Ok(Some("echo two".into())));
+        assert_eq!(expand(&commands, "!42"), Err("!42: event not found".into()));
+        assert_eq!(expand(&commands, "!0"), Err("!0: event not found".into()));
+    }
+
+    #[test]
+    fn prefix_events_find_the_latest_match() {
+        let commands = ["echo one", "ls", "echo two"];
+        assert_eq!(expand(&commands, "!ec"), Ok(Some("echo two".into())));
+        assert_eq!(expand(&commands, "!l; !e"), Ok(Some("ls; echo two".into())));
+        assert_eq!(
+            expand(&commands, "!cat"),
+            Err("!cat: event not found".into())
+        );
+    }
+
+    #[test]
+    fn lines_without_events_are_left_alone() {
+        let commands = ["echo one"];
+        assert_eq!(expand(&commands, "echo hi"), Ok(None));
+        assert_eq!(expand(&commands, "echo ! !="), Ok(None));
+        assert_eq!(expand(&commands, "echo '!!' \\!!"), Ok(None));
+    }
+
+    #[test]
+    fn print_modifier() {
+        let mut history = History::default();
+        history.add("echo one", "", DEFAULT_SIZE);
+        let expansion = history.expand("!ec:p").unwrap().unwrap();
+        assert_eq!(expansion.line, "echo one");
+        assert!(expansion.print_only);
+        let expansion = history.expand("!!:p | cat").unwrap().unwrap();
+        assert_eq!(expansion.line, "echo one | cat");
+        assert!(expansion.print_only);
+        assert!(!history.expand("!!").unwrap().unwrap().print_only);
+    }
+
+    #[test]
+    fn searches_go_either_way_from_an_entry() {
+        let mut history = History::default();
+        for command in ["echo alpha", "echo beta", "echo alps"] {
+            history.add(command, "", DEFAULT_SIZE);
+        }
+        assert_eq!(history.search("al", 3), Some(2));
+        assert_eq!(history.search("al", 2), Some(0));
+        assert_eq!(history.search("al", 0), None);
+        assert_eq!(history.search("al", 10), Some(2));
+        assert_eq!(history.search_forward("al", 0), Some(0));
+        assert_eq!(history.search_forward("al", 1), Some(2));
+        assert_eq!(history.search_forward("al", 3), None);
+        assert_eq!(history.search_forward("beta", 10), None);
+    }
+
+    #[test]
+    fn files_round_trip_and_append_only_what_is_new() {
+        let path = std::env::temp_dir().join(format!("history-{}", std::process::id()));
+        let mut history = History::default();
+        history.add("a", "", DEFAULT_SIZE);
+        history.add("b", "", DEFAULT_SIZE);
+        history.write(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+
+        history.add("c", "", DEFAULT_SIZE);
+        history.append(&path).unwrap();
+        history.append(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\nc\n");
+
+        let mut read = History::default();
+        read.read(&path, 2).unwrap();
+        assert_eq!(read.entries, ["b", "c"]);
+        read.add("d", "", DEFAULT_SIZE);
+        read.append(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\nc\nd\n");
+        fs::remove_file(&path).unwrap();
+        assert!(read.read(&path, DEFAULT_SIZE).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use std::{io, os::raw::c_int};
+
+use crate::signal::{self, SIGCONT};
+
+/// A pipeline stage that has been started but not necessarily finished, with the id of its
+/// process. It is either a program or a copy of the shell running a builtin.
+pub enum Stage {
+    Running(u32),
+    Stopped(u32),
+    Done(i32),
+}
+
+impl Stage {
+    /// The id of the stage's process, until it has been reaped.
+    fn pid(&self) -> Option<u32> {
+        match self {
+            Stage::Running(pid) | Stage::Stopped(pid) => Some(*pid),
+            Stage::Done(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+/// A pipeline whose processes share a process group.
+pub struct Job {
+    /// The job number, assigned when the job is added to the jobs table.
+    pub id: usize,
+    pub command: String,
+    pub group: Option<i32>,
+    stages: Vec<Stage>,
+}
+
+impl Job {
+    pub fn new(command: String, group: Option<i32>, stages: Vec<Stage>) -> Self {
+        Job {
+            id: 0,
+            command,
+            group,
+            stages,
+        }
+    }
+
+    /// The process id of the last stage, which is what job listings refer to.
+    pub fn pid(&self) -> Option<u32> {
+        self.stages.iter().rev().find_map(Stage::pid)
+    }
+
+    /// A job is stopped if any stage is, and done with the last stage's status once all are.
+    pub fn state(&self) -> JobState {
+        let mut state = JobState::Done(0);
+        for stage in &self.stages {
+            state = match (state, stage) {
+                (JobState::Stopped, _) | (_, Stage::Stopped(_)) => JobState::Stopped,
+                (_, Stage::Running(_)) => JobState::Running,
+                (JobState::Running, Stage::Done(_)) => JobState::Running,
+                (JobState::Done(_), Stage::Done(status)) => JobState::Done(*status),
+            };
+        }
+        state
+    }
+
+    /// The exit status of every stage, or `None` until they have all finished.
+    pub fn statuses(&self) -> Option<Vec<i32>> {
+        self.stages
+            .iter()
+            .map(|stage| match stage {
+                Stage::Done(status) => Some(*status),
+                Stage::Running(_) | Stage::Stopped(_) => None,
+            })
+            .collect()
+    }
+
+    /// Blocks until every stage has exited or one of them has stopped.
+    pub fn wait(&mut self) -> io::Result<()> {
+        self.update(true)
+    }
+
+    /// Collects stages that have exited or stopped without blocking.
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.update(false)
+    }
+
+    fn update(&mut self, block: bool) -> io::Result<()> {
+        for stage in &mut self.stages {
+            let Stage::Running(pid) = *stage else {
+                continue;
+            };
+            match waitpid(pid as i32, block)? {
+                Some(WaitStatus::Exited(status)) => *stage = Stage::Done(status),
+                Some(WaitStatus::Stopped) => {
+                    *stage = Stage::Stopped(pid);
+                    // The whole group stops together, so there is nothing left to wait for.
+                    if block {
+                        break;
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Continues a stopped job.
+    pub fn resume(&mut self) -> io::Result<()> {
+        if let Some(group) = self.group {
+            signal::kill(-group, SIGCONT)?;
+        }
+        for stage in &mut self.stages {
+            if let Stage::Stopped(pid) = *stage {
+                *stage = Stage::Running(pid);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The jobs table. Jobs are kept from least to most recently started or stopped, which is
+/// what decides the current job.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Starts tracking a job as the current one, numbering it after the highest job id still
+    /// in use unless it already has an id.
+    pub fn add(&mut self, mut job: Job) -> &Job {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        self.jobs.push(job);
+        &self.jobs[self.jobs.len() - 1]
+    }
+
+    /// The tracked jobs in order of their ids.
+    pub fn list(&self) -> Vec<&Job> {
+        let mut jobs: Vec<_> = self.jobs.iter().collect();
+        jobs.sort_by_key(|job| job.id);
+        jobs
+    }
+
+    /// Resolves a job spec like `%1`, `%+` or `%-` to a job id. Without a spec, or with `%%`
+    /// or `%+`, it refers to the current job.
+    pub fn find(&self, spec: Option<&str>) -> Option<usize> {
+        let mut recent = self.jobs.iter().rev().map(|job| job.id);
+        match spec {
+            None | Some("%%" | "%+") => recent.next(),
+            Some("%-") => recent.nth(1),
+            Some(spec) => {
+                let id = spec.strip_prefix('%').unwrap_or(spec).parse().ok()?;
+                self.jobs.iter().any(|job| job.id == id).then_some(id)
+            }
+        }
+    }
+
+    /// Finds the job with a stage that is the process `pid`.
+    pub fn find_pid(&self, pid: u32) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.stages.iter().any(|stage| stage.pid() == Some(pid)))
+            .map(|job| job.id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// Collects the state changes of every job without blocking.
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.jobs.iter_mut().try_for_each(Job::poll)
+    }
+
+    /// Removes the jobs that have finished, returning them with their exit statuses.
+    pub fn reap(&mut self) -> io::Result<Vec<(Job, i32)>> {
+        self.poll()?;
+        let mut finished = vec![];
+        let mut i = 0;
+        while i < self.jobs.len() {
+            match self.jobs[i].state() {
+                JobState::Done(status) => finished.push((self.jobs.remove(i), status)),
+                _ => i += 1,
+            }
+        }
+        Ok(finished)
+    }
+
+    /// The marker shown next to a job id: `+` for the current job and `-` for the previous
+    /// one.
+    pub fn marker(&self, id: usize) -> char {
+        let mut recent = self.jobs.iter().rev().map(|job| job.id);
+        match (recent.next(), recent.next()) {
+            (Some(current), _) if current == id => '+',
+            (_, Some(previous)) if previous == id => '-',
+            _ => ' ',
+        }
+    }
+}
+
+enum WaitStatus {
+    Exited(i32),
+    Stopped,
+}
+
+const WNOHANG: c_int = 1;
+const ECHILD: i32 = 10;
+const WUNTRACED: c_int = 2;
+
+extern "C" {
+    #[link_name = "waitpid"]
+    fn sys_waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int;
+    #[link_name = "tcsetpgrp"]
+    fn sys_tcsetpgrp(fd: c_int, pgrp: c_int) -> c_int;
+    #[link_name = "getpgrp"]
+    fn sys_getpgrp() -> c_int;
+    #[link_name = "fork"]
+    fn sys_fork() -> c_int;
+    #[link_name = "setpgid"]
+    fn sys_setpgid(pid: c_int, pgid: c_int) -> c_int;
+}
+
+/// Forks the shell, returning the child's pid in the parent and `0` in the child.
+pub fn fork() -> io::Result<i32> {
+    // SAFETY: the shell has no other threads holding locks the child could need; the only
+    // ones it starts just feed here-documents to pipes.
+    match unsafe { sys_fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        pid => Ok(pid),
+    }
+}
+
+/// Puts the process `pid` in the process group `group`, or a new one of its own if `group`
+/// is `0`. Both a forked child and the shell do this, so that the group exists whichever
+/// gets there first; the one that comes second fails harmlessly.
+pub fn set_group(pid: i32, group: i32) {
+    // SAFETY: `setpgid` has no memory-safety preconditions.
+    unsafe {
+        sys_setpgid(pid, group);
+    }
+}
+
+/// Waits for a forked child to exit and returns its status.
+pub fn wait_for(pid: i32) -> io::Result<i32> {
+    loop {
+        if let Some(WaitStatus::Exited(status)) = waitpid(pid, true)? {
+            return Ok(status);
+        }
+    }
+}
+
+/// Waits for a child to exit or stop, returning `None` if it hasn't yet and `block` is off.
+/// Children killed by a signal report `128 + signal` like shells do.
+fn waitpid(pid: i32, block: bool) -> io::Result<Option<WaitStatus>> {
+    let options = if block {
+        WUNTRACED
+    } else {
+        WUNTRACED | WNOHANG
+    };
+    let mut status = 0;
+    loop {
+        // SAFETY: `status` is a valid pointer for the duration of the call.
+        match unsafe { sys_waitpid(pid, &mut status, options) } {
+            0 => return Ok(None),
+            -1 => {
+                let e = io::Error::last_os_error();
+                // A command substitution can't wait for the jobs of the shell it was
+                // forked from, which still counts them as running.
+                if !block && e.raw_os_error() == Some(ECHILD) {
+                    return Ok(None);
+                }
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            _ => break,
+        }
+    }
+    Ok(Some(match status & 0x7f {
+        0 => WaitStatus::Exited((status >> 8) & 0xff),
+        0x7f => WaitStatus::Stopped,
+        sig => WaitStatus::Exited(128 + sig),
+    }))
+}
+
+/// Makes `group` the terminal's foreground process group. Failing because stdin isn't a
+/// terminal is expected when the shell isn't interactive, so that case is ignored.
+pub fn set_foreground(group: i32) {
+    // SAFETY: `tcsetpgrp` has no memory-safety preconditions.
+    unsafe {
+        sys_tcsetpgrp(0, group);
+    }
+}
+
+/// The calling process's group.
+pub fn current_group() -> i32 {
+    // SAFETY: `getpgrp` always succeeds.
+    unsafe { sys_getpgrp() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs(pids: &[u32]) -> Jobs {
+        let mut jobs = Jobs::default();
+        for &pid in pids {
+            let job = Job::new(format!("job {}", pid), None, vec![Stage::Running(pid)]);
+            jobs.add(job);
+        }
+        jobs
+    }
+
+    #[test]
+    fn job_specs() {
+        let jobs = jobs(&[10, 20, 30]);
+        assert_eq!(jobs.find(None), Some(3));
+        assert_eq!(jobs.find(Some("%%")), Some(3));
+        assert_eq!(jobs.find(Some("%+")), Some(3));
+        assert_eq!(jobs.find(Some("%-")), Some(2));
+        assert_eq!(jobs.find(Some("%1")), Some(1));
+        assert_eq!(jobs.find(Some("%4")), None);
+        assert_eq!(jobs.find(Some("%x")), None);
+        assert_eq!(jobs.find_pid(20), Some(2));
+        assert_eq!(jobs.find_pid(40), None);
+    }
+
+    #[test]
+    fn a_job_is_known_by_its_last_stage() {
+        let job = Job::new(
+            "a | b".to_string(),
+            Some(10),
+            vec![Stage::Running(10), Stage::Running(11)],
+        );
+        assert_eq!(job.pid(), Some(11));
+        assert_eq!(job.state(), JobState::Running);
+        let job = Job::new("a".to_string(), None, vec![Stage::Done(3)]);
+        assert_eq!(job.pid(), None);
+        assert_eq!(job.state(), JobState::Done(3));
+    }
+
+    #[test]
+    fn ids_follow_the_highest_in_use() {
+        let mut jobs = jobs(&[10, 20]);
+        jobs.remove(1);
+        assert_eq!(jobs.add(Job::new("c".to_string(), None, vec![])).id, 3);
+        jobs.remove(3);
+        jobs.remove(2);
+        assert_eq!(jobs.add(Job::new("d".to_string(), None, vec![])).id, 1);
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use std::{collections::VecDeque, ops::Range, vec};
+
+use crate::token::{tokenize, Expand, SyntaxError, Token};
+
+/// A command line grouped by its list operators. `|` binds tightest, inside the text of a
+/// pipeline, then `&&` and `||`, which associate to the left, then `;` and `&`, so that
+/// `a | b && c | d ; e` is `(a | b) && (c | d)` followed by `e`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum List {
+    Pipeline(Pipeline),
+    /// Runs the right side only if the left one succeeds.
+    And(Box<List>, Box<List>),
+    /// Runs the right side only if the left one fails.
+    Or(Box<List>, Box<List>),
+    /// Runs one side after the other.
+    Sequence(Box<List>, Box<List>),
+    /// Runs a list as a job without waiting for it, named by its text without the `&`.
+    Background(Box<List>, String),
+}
+
+impl List {
+    /// The pipeline that runs first.
+    pub fn first_mut(&mut self) -> &mut Pipeline {
+        match self {
+            List::Pipeline(pipeline) => pipeline,
+            List::And(first, _)
+            | List::Or(first, _)
+            | List::Sequence(first, _)
+            | List::Background(first, _) => first.first_mut(),
+        }
+    }
+}
+
+/// The text of a pipeline, which is only expanded and split into stages once it runs, since
+/// what it expands to can depend on the commands before it, as in `x=1; echo $x`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub text: String,
+    /// The bodies of its here-documents, in order.
+    pub heredocs: VecDeque<String>,
+    /// Whether an alias naming its command is expanded, which is off for the text an alias
+    /// expanded to already.
+    pub aliases: bool,
+}
+
+/// Parses `input` into a list, or `None` if there is no command in it, taking the bodies of
+/// the here-documents it uses from `heredocs`. Parameters are left unexpanded and nothing
+/// runs. On a single line the column of a syntax error counts from the start of the line
+/// rather than of the pipeline it is in.
+pub fn parse(input: &str, heredocs: &mut VecDeque<String>) -> Result<Option<List>, SyntaxError> {
+    let mut parser = Parser {
+        input,
+        commands: split(input, heredocs)?.into_iter(),
+    };
+    Ok(parser.list())
+}
+
+/// A pipeline with the list operator that ends it, if any, and where its text is in the
+/// input.
+struct Command {
+    pipeline: Pipeline,
+    op: Option<Token>,
+    span: Range<usize>,
+}
+
+/// Splits `input` at its list operators, which the lexer stops at.
+fn split(input: &str, heredocs: &mut VecDeque<String>) -> Result<Vec<Command>, SyntaxError> {
+    let mut commands = vec![];
+    let mut rest = input;
+    while !rest.trim().is_empty() {
+        let start = input.len() - rest.len();
+        let mut unread = heredocs.clone();
+        let (tokens, after) = match tokenize(rest, &mut Unexpanded, &mut unread) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let mut e = match e.downcast::<SyntaxError>() {
+                    Ok(e) => e,
+                    // Anything else, like a bad substitution, is left for running the rest
+                    // to report, after the commands before it.
+                    Err(_) => {
+                        commands.push(Command {
+                            pipeline: pipeline(rest, std::mem::take(heredocs)),
+                            op: None,
+                            span: start..input.len(),
+                        });
+                        break;
+                    }
+                };
+                if !rest.trim_end_matches('\n').contains('\n') {
+                    let before = &input[..start];
+                    e.column += before.chars().rev().take_while(|&c| c != '\n').count();
+                }
+                return Err(e);
+            }
+        };
+        let op = match tokens.last() {
+            Some(op @ (Token::Semicolon | Token::AndIf | Token::OrIf | Token::Background)) => {
+                Some(op.clone())
+            }
+            _ => None,
+        };
+        // The operator is the last thing read.
+        let end = input.len()
+            - after.len()
+            - match op {
+                Some(Token::AndIf | Token::OrIf) => 2,
+                Some(_) => 1,
+                None => 0,
+            };
+        let used = heredocs.len() - unread.len();
+        commands.push(Command {
+            pipeline: pipeline(&input[start..end], heredocs.drain(..used).collect()),
+            op,
+            span: start..end,
+        });
+        rest = after;
+    }
+    Ok(commands)
+}
+
+fn pipeline(text: &str, heredocs: VecDeque<String>) -> Pipeline {
+    Pipeline {
+        text: text.to_string(),
+        heredocs,
+        aliases: true,
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    commands: vec::IntoIter<Command>,
+}
+
+impl Parser<'_> {
+    /// list := and_or ((`;` | `&`) and_or)*
+    fn list(&mut self) -> Option<List> {
+        let mut list = None;
+        while let Some((mut and_or, op, span)) = self.and_or() {
+            if op == Some(Token::Background) {
+                let command = self.input[span].trim().to_string();
+                and_or = List::Background(Box::new(and_or), command);
+            }
+            list = Some(match list {
+                Some(first) => List::Sequence(Box::new(first), Box::new(and_or)),
+                None => and_or,
+            });
+        }
+        list
+    }
+
+    /// and_or := pipeline ((`&&` | `||`) pipeline)*
+    ///
+    /// Returns the operator after the last pipeline, and where the whole of it is in the
+    /// input.
+    fn and_or(&mut self) -> Option<(List, Option<Token>, Range<usize>)> {
+        let first = self.commands.next()?;
+        let mut span = first.span;
+        let mut op = first.op;
+        let mut and_or = List::Pipeline(first.pipeline);
+        while let Some(Token::AndIf | Token::OrIf) = op {
+            // The lexer only ends a command at `&&` or `||` with another one after it.
+            let Some(next) = self.commands.next() else {
+                break;
+            };
+            let right = Box::new(List::Pipeline(next.pipeline));
+            and_or = match op {
+                Some(Token::AndIf) => List::And(Box::new(and_or), right),
+                _ => List::Or(Box::new(and_or), right),
+            };
+            span.end = next.span.end;
+            op = next.op;
+        }
+        Some((and_or, op, span))
+    }
+}
+
+/// Expands every parameter to nothing without running any command, for parsing input
+/// before it runs.
+struct Unexpanded;
+
+impl Expand for Unexpanded {
+    fn lookup(&self, _: &str) -> Option<String> {
+        None
+    }
+
+    fn substitute(&mut self, _: &str) -> Result<String> {
+        Ok(String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(input: &str) -> List {
+        parse(input, &mut VecDeque::new()).unwrap().unwrap()
+    }
+
+    fn leaf(text: &str) -> Box<List> {
+        Box::new(List::Pipeline(pipeline(text, VecDeque::new())))
+    }
+
+    #[test]
+    fn pipes_bind_tighter_than_and_or_and_those_tighter_than_semicolons() {
+        assert_eq!(
+            parsed("a | b && c | d ; e"),
+            List::Sequence(
+                Box::new(List::And(leaf("a | b "), leaf(" c | d "))),
+                leaf(" e")
+            )
+        );
+    }
+
+    #[test]
+    fn and_or_associate_to_the_left() {
+        assert_eq!(
+            parsed("a || b && c"),
+            List::And(Box::new(List::Or(leaf("a "), leaf(" b "))), leaf(" c"))
+        );
+    }
+
+    #[test]
+    fn background_takes_the_whole_and_or_list() {
+        assert_eq!(
+            parsed("a && b & c;"),
+            List::Sequence(
+                Box::new(List::Background(
+                    Box::new(List::And(leaf("a "), leaf(" b "))),
+                    "a && b".to_string()
+                )),
+                leaf(" c")
+            )
+        );
+    }
+
+    #[test]
+    fn pipelines_take_their_own_heredocs() {
+        let mut heredocs = VecDeque::from(["one\n".to_string(), "two\n".to_string()]);
+        let mut list = parse("cat <<A; cat <<B", &mut heredocs).unwrap().unwrap();
+        assert!(heredocs.is_empty());
+        assert_eq!(list.first_mut().heredocs, ["one\n"]);
+        let List::Sequence(_, second) = list else {
+            panic!("not a sequence");
+        };
+        assert_eq!(
+            *second,
+            List::Pipeline(pipeline(" cat <<B", ["two\n".into()].into()))
+        );
+    }
+
+    #[test]
+    fn errors_count_columns_from_the_start_of_the_line() {
+        let e = parse("echo a; echo b |;", &mut VecDeque::new()).unwrap_err();
+        assert_eq!(e.column, 17);
+        assert!(
+            parse("echo a &&", &mut VecDeque::new())
+                .unwrap_err()
+                .incomplete
+        );
+        assert_eq!(parse("  ", &mut VecDeque::new()).unwrap(), None);
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::{anyhow, bail, Result};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    mem,
+    os::{raw::c_int, unix::io::FromRawFd},
+};
+
+use crate::{
+    command::{strerror, Command, Input, Output},
+    jobs::{self, Job, Stage},
+    signal,
+    state::Terminal,
+    token::{RedirectType, Token},
+};
+
+pub struct Pipeline {
+    /// The stages in order, or the error from a redirection of one that couldn't be set up.
+    commands: Vec<Result<Command>>,
+    // background: bool,
+}
+
+impl Pipeline {
+    /// Builds the stages of a pipeline, the last of which writes to `stdout` unless it's
+    /// redirected. With `noclobber`, `>` refuses to overwrite a file that exists. A stage
+    /// whose redirections fail is kept as the error, which only fails that stage.
+    pub fn from_tokens(tokens: Vec<Token>, stdout: Output, noclobber: bool) -> Result<Self> {
+        // Redirections are applied once the pipes are in place, since `2>&1` refers to
+        // wherever stdout goes at that point.
+        let mut stages = vec![];
+        let mut cmd = new_command();
+        let mut redirects = vec![];
+
+        for token in tokens {
+            match token {
+                Token::Arg(arg) => push_arg(&mut cmd, arg),
+                Token::Assignment { name, value } => cmd.env.push((name, value)),
+                Token::Redirect { .. }
+                | Token::Duplicate { .. }
+                | Token::HereDoc(_)
+                | Token::AmbiguousRedirect(_) => redirects.push(token),
+                Token::Semicolon | Token::AndIf | Token::OrIf | Token::Background => {
+                    bail!("syntax error: unexpected list operator")
+                }
+                Token::Pipe => {
+                    if cmd.name.is_empty() && cmd.env.is_empty() && redirects.is_empty() {
+                        bail!("syntax error near unexpected token `|'");
+                    }
+                    stages.push((
+                        mem::replace(&mut cmd, new_command()),
+                        mem::take(&mut redirects),
+                    ));
+                }
+            }
+        }
+
+        // Redirections on their own still create files, as in `> file`.
+        if !cmd.name.is_empty() || !cmd.env.is_empty() || !redirects.is_empty() {
+            stages.push((cmd, redirects));
+        } else if !stages.is_empty() {
+            bail!("syntax error: unexpected end of input after `|'");
+        }
+
+        let count = stages.len();
+        let mut commands = Vec::with_capacity(count);
+        let mut reader = None;
+        for (i, (mut cmd, redirects)) in stages.into_iter().enumerate() {
+            if let Some(reader) = reader.take() {
+                cmd.input = Input::Pipe(reader);
+            }
+            if i + 1 < count {
+                let (read, write) = pipe()?;
+                cmd.out = Output::File(write);
+                reader = Some(read);
+            } else {
+                cmd.out = stdout.try_clone()?;
+            }
+            let applied = redirects
+                .into_iter()
+                .try_for_each(|redirect| apply_redirect(&mut cmd, redirect, noclobber));
+            commands.push(applied.map(|_| cmd));
+        }
+        Ok(Pipeline { commands })
+    }
+
+    /// Starts every stage as a job named `command` without waiting for them. A builtin on its
+    /// own in the foreground runs to completion in the shell itself, but one sharing a
+    /// pipeline or in the background runs in a copy of the shell, as it runs alongside
+    /// everything else and anything it changes should stay in the job, like `cd` or `exit`
+    /// would. The processes share a process group of their own, which a foreground job is
+    /// handed the terminal for, so that Ctrl+C and Ctrl+Z only reach the job the user is
+    /// interacting with. Under `set -x` each stage is printed to stderr as it will run.
+    pub fn spawn(mut self, term: &mut Terminal, command: &str, foreground: bool) -> Result<Job> {
+        let mut stages = Vec::with_capacity(self.commands.len());
+        let in_subshell = !foreground || self.commands.len() > 1;
+        // In a copy of the shell running a list in the background, every stage joins its
+        // process group and stays in the background with it.
+        let mut group = term.job_group;
+        let foreground = foreground && group.is_none();
+
+        // Without a terminal to stop them at, background jobs would read the script the
+        // shell is running, so they read nothing instead, as in other shells.
+        if !foreground && !term.interactive {
+            for cmd in self.commands.iter_mut().flatten() {
+                if let Input::Stdin = cmd.input {
+                    cmd.input = Input::File(File::open("/dev/null")?);
+                }
+            }
+        }
+
+        if term.options.xtrace {
+            for cmd in self.commands.iter().flatten() {
+                eprintln!("+ {}", cmd.trace());
+            }
+        }
+
+        // Dropping each command once it has started closes the shell's copies of its pipe
+        // ends, so the stages see the end of their input when the one before them finishes.
+        let mut commands = self.commands.into_iter();
+        while let Some(cmd) = commands.next() {
+            let mut cmd = match cmd {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    stages.push(Stage::Done(1));
+                    continue;
+                }
+            };
+            let builtin = cmd.is_builtin() || cmd.name.is_empty();
+            if builtin && !in_subshell {
+                let status = run_builtin(cmd, term)?;
+                stages.push(Stage::Done(status));
+                continue;
+            }
+            let started = match builtin {
+                true => fork_builtin(cmd, term, &mut commands, group, foreground)?,
+                false => cmd
+                    .spawn(Some(group.unwrap_or(0)), foreground)?
+                    .map(|c| c.id()),
+            };
+            stages.push(match started {
+                Ok(pid) => {
+                    if group.is_none() {
+                        group = Some(pid as i32);
+                    }
+                    Stage::Running(pid)
+                }
+                Err(status) => Stage::Done(status),
+            });
+        }
+        Ok(Job::new(command.to_string(), group, stages))
+    }
+}
+
+/// Runs a builtin in the shell, returning its status.
+fn run_builtin(mut cmd: Command, term: &mut Terminal) -> Result<i32> {
+    match cmd.execute(term) {
+        Ok(status) => Ok(status),
+        // The reader went away early, as in `export | head -1`.
+        Err(e) if is_broken_pipe(&e) => Ok(1),
+        Err(e) => Err(e),
+    }
+}
+
+/// Starts a builtin in a copy of the shell that exits with its status, in the process group
+/// `group` or a new one, returning the copy's process id. The stages still to start are
+/// closed in the copy, which would otherwise keep their pipes open.
+fn fork_builtin(
+    cmd: Command,
+    term: &mut Terminal,
+    rest: &mut dyn Iterator<Item = Result<Command>>,
+    group: Option<i32>,
+    foreground: bool,
+) -> Result<std::result::Result<u32, i32>> {
+    io::stdout().flush()?;
+    let pid = jobs::fork()?;
+    if pid != 0 {
+        jobs::set_group(pid, group.unwrap_or(pid));
+        return Ok(Ok(pid as u32));
+    }
+
+    rest.for_each(drop);
+    jobs::set_group(0, group.unwrap_or(0));
+    if foreground {
+        jobs::set_foreground(jobs::current_group());
+    }
+    signal::restore_defaults();
+    term.subshell = true;
+    let status = run_builtin(cmd, term).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        1
+    });
+    term.exit(status);
+}
+
+fn new_command() -> Command {
+    Command {
+        name: String::new(),
+        args: vec![],
+        input: Input::Stdin,
+        out: Output::Stdout,
+        err: Output::Stderr,
+        env: vec![],
+    }
+}
+
+fn push_arg(cmd: &mut Command, arg: String) {
+    if cmd.name.is_empty() {
+        cmd.name = arg;
+    } else {
+        cmd.args.push(arg);
+    }
+}
+
+fn apply_redirect(cmd: &mut Command, redirect: Token, noclobber: bool) -> Result<()> {
+    match redirect {
+        Token::Redirect {
+            kind: RedirectType::Stdin,
+            path,
+            ..
+        } => {
+            let file = File::open(&path).map_err(|e| anyhow!("{}: {}", path, strerror(&e)))?;
+            cmd.input = Input::File(file);
+        }
+        // These name wherever the command's own output goes by now, which isn't the shell's
+        // when it writes into a pipe or a command substitution.
+        Token::Redirect { kind, path, .. }
+            if matches!(path.as_str(), "/dev/stdout" | "/dev/stderr") =>
+        {
+            let output = match path.as_str() {
+                "/dev/stdout" => cmd.out.try_clone()?,
+                _ => cmd.err.try_clone()?,
+            };
+            match kind {
+                RedirectType::Stdout => cmd.out = output,
+                RedirectType::Stderr => cmd.err = output,
+                RedirectType::Both => {
+                    cmd.out = output.try_clone()?;
+                    cmd.err = output;
+                }
+                RedirectType::Stdin => unreachable!(),
+            }
+        }
+        // `>|` and `>>` still write to the file.
+        Token::Redirect {
+            path,
+            append: false,
+            force: false,
+            ..
+        } if noclobber && fs::metadata(&path).is_ok_and(|m| m.is_file()) => {
+            bail!("{}: cannot overwrite existing file", path);
+        }
+        Token::Redirect {
+            kind, path, append, ..
+        } => {
+            let file =
+                create_file(&path, append).map_err(|e| anyhow!("{}: {}", path, strerror(&e)))?;
+            match kind {
+                RedirectType::Stdout => cmd.out = Output::File(file),
+                RedirectType::Stderr => cmd.err = Output::File(file),
+                RedirectType::Both => {
+                    cmd.out = Output::File(file.try_clone()?);
+                    cmd.err = Output::File(file);
+                }
+                RedirectType::Stdin => unreachable!(),
+            }
+        }
+        Token::HereDoc(body) => cmd.input = Input::Bytes(io::Cursor::new(body.into_bytes())),
+        Token::AmbiguousRedirect(text) => bail!("{}: ambiguous redirect", text),
+        Token::Duplicate { kind, target } => {
+            let output = match target {
+                RedirectType::Stdout => cmd.out.try_clone()?,
+                RedirectType::Stderr => cmd.err.try_clone()?,
+                RedirectType::Stdin | RedirectType::Both => unreachable!(),
+            };
+            match kind {
+                RedirectType::Stdout => cmd.out = output,
+                RedirectType::Stderr => cmd.err = output,
+                RedirectType::Stdin | RedirectType::Both => unreachable!(),
+            }
+        }
+        Token::Arg(_)
+        | Token::Assignment { .. }
+        | Token::Pipe
+        | Token::Semicolon
+        | Token::AndIf
+        | Token::OrIf
+        | Token::Background => {
+            unreachable!()
+        }
+    }
+    Ok(())
+}
+
+fn create_file(path: &str, append: bool) -> io::Result<File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(!append)
+        .append(append)
+        .open(path)
+}
+
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+extern "C" {
+    #[link_name = "pipe"]
+    fn sys_pipe(fds: *mut c_int) -> c_int;
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+}
+
+const F_SETFD: c_int = 2;
+const FD_CLOEXEC: c_int = 1;
+
+/// Creates an OS pipe, returning its read and write ends. Both are close-on-exec so that
+/// children only see the ends explicitly handed to them.
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    // SAFETY: on success `pipe` hands back two fresh descriptors that nothing else owns.
+    unsafe {
+        if sys_pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ends = (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]));
+        for fd in fds {
+            if fcntl(fd, F_SETFD, FD_CLOEXEC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(ends)
+    }
+}
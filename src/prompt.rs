@@ -0,0 +1,118 @@
+use std::{
+    env, fs,
+    os::raw::{c_char, c_int},
+};
+
+use crate::command::working_dir;
+
+extern "C" {
+    fn geteuid() -> u32;
+    fn gethostname(name: *mut c_char, len: usize) -> c_int;
+}
+
+/// Expands the escapes in a `PS1` style prompt: `\w` for the current directory with the
+/// home directory shortened to `~`, `\u` for the user name, `\h` for the host name up to
+/// the first dot, and `\$` for `#` when running as root and `$` otherwise. Anything else is
+/// kept as it is.
+pub fn expand(template: &str) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('w') => prompt.push_str(&directory()),
+            Some('u') => prompt.push_str(&user()),
+            Some('h') => prompt.push_str(host().split('.').next().unwrap_or_default()),
+            Some('$') => prompt.push(if uid() == 0 { '#' } else { '$' }),
+            Some('\\') => prompt.push('\\'),
+            Some(other) => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+            None => prompt.push('\\'),
+        }
+    }
+    prompt
+}
+
+fn directory() -> String {
+    match working_dir() {
+        Ok(dir) => abbreviate_home(&dir.to_string_lossy()),
+        Err(_) => String::new(),
+    }
+}
+
+/// Shortens a directory in the home directory to start with `~` instead.
+pub fn abbreviate_home(dir: &str) -> String {
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() && dir == home => "~".to_string(),
+        Ok(home) if !home.is_empty() && dir.starts_with(&format!("{}/", home)) => {
+            format!("~{}", &dir[home.len()..])
+        }
+        _ => dir.to_string(),
+    }
+}
+
+fn uid() -> u32 {
+    // SAFETY: `geteuid` has no preconditions and always succeeds.
+    unsafe { geteuid() }
+}
+
+/// The name the password database has for the user, or `USER` if it has none.
+fn user() -> String {
+    let uid = uid().to_string();
+    let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[2] == uid)
+        .map(|fields| fields[0].to_string())
+        .or_else(|| env::var("USER").ok())
+        .unwrap_or_default()
+}
+
+fn host() -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: the length passed leaves the last byte of the buffer unwritten, so the name
+    // stays terminated even if it was cut short.
+    if unsafe { gethostname(name.as_mut_ptr().cast(), name.len() - 1) } != 0 {
+        return String::new();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let dollar = if uid() == 0 { "#" } else { "$" };
+        assert_eq!(expand("\\$ "), format!("{} ", dollar));
+        assert_eq!(expand("a\\\\b \\q \\"), "a\\b \\q \\");
+        assert_eq!(expand("\\u"), user());
+        assert_eq!(expand("\\h"), host().split('.').next().unwrap_or_default());
+        assert_eq!(expand("plain $ "), "plain $ ");
+    }
+
+    #[test]
+    fn home_is_shortened() {
+        let Ok(home) = env::var("HOME") else {
+            return;
+        };
+        if home.is_empty() {
+            return;
+        }
+        assert_eq!(abbreviate_home(&home), "~");
+        assert_eq!(abbreviate_home(&format!("{}/src", home)), "~/src");
+        assert_eq!(
+            abbreviate_home(&format!("{}src", home)),
+            format!("{}src", home)
+        );
+        assert_eq!(abbreviate_home("/"), "/");
+    }
+}
@@ -0,0 +1,192 @@
+use std::{io, os::raw::c_int};
+
+pub const SIGINT: c_int = 2;
+pub const SIGTERM: c_int = 15;
+
+#[cfg(not(target_os = "macos"))]
+mod platform {
+    use std::os::raw::c_int;
+
+    pub const SIGCONT: c_int = 18;
+    pub const SIGTSTP: c_int = 20;
+
+    pub const NAMES: &[(&str, c_int)] = &[
+        ("HUP", 1),
+        ("INT", 2),
+        ("QUIT", 3),
+        ("ILL", 4),
+        ("TRAP", 5),
+        ("ABRT", 6),
+        ("BUS", 7),
+        ("FPE", 8),
+        ("KILL", 9),
+        ("USR1", 10),
+        ("SEGV", 11),
+        ("USR2", 12),
+        ("PIPE", 13),
+        ("ALRM", 14),
+        ("TERM", 15),
+        ("CHLD", 17),
+        ("CONT", 18),
+        ("STOP", 19),
+        ("TSTP", 20),
+        ("TTIN", 21),
+        ("TTOU", 22),
+        ("URG", 23),
+        ("XCPU", 24),
+        ("XFSZ", 25),
+        ("VTALRM", 26),
+        ("PROF", 27),
+        ("WINCH", 28),
+        ("IO", 29),
+        ("SYS", 31),
+    ];
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::os::raw::c_int;
+
+    pub const SIGCONT: c_int = 19;
+    pub const SIGTSTP: c_int = 18;
+
+    pub const NAMES: &[(&str, c_int)] = &[
+        ("HUP", 1),
+        ("INT", 2),
+        ("QUIT", 3),
+        ("ILL", 4),
+        ("TRAP", 5),
+        ("ABRT", 6),
+        ("EMT", 7),
+        ("FPE", 8),
+        ("KILL", 9),
+        ("BUS", 10),
+        ("SEGV", 11),
+        ("SYS", 12),
+        ("PIPE", 13),
+        ("ALRM", 14),
+        ("TERM", 15),
+        ("URG", 16),
+        ("STOP", 17),
+        ("TSTP", 18),
+        ("CONT", 19),
+        ("CHLD", 20),
+        ("TTIN", 21),
+        ("TTOU", 22),
+        ("IO", 23),
+        ("XCPU", 24),
+        ("XFSZ", 25),
+        ("VTALRM", 26),
+        ("PROF", 27),
+        ("WINCH", 28),
+        ("INFO", 29),
+        ("USR1", 30),
+        ("USR2", 31),
+    ];
+}
+
+pub use platform::{SIGCONT, SIGTSTP};
+
+/// The signals known by name, without their `SIG` prefix, in order of their numbers.
+pub fn names() -> &'static [(&'static str, c_int)] {
+    platform::NAMES
+}
+
+/// The signal a name like `TERM`, `SIGTERM` or `term`, or a number, stands for.
+pub fn parse(spec: &str) -> Option<c_int> {
+    if let Ok(sig) = spec.parse() {
+        return (sig == 0 || name(sig).is_some()).then_some(sig);
+    }
+    let spec = spec.to_ascii_uppercase();
+    let spec = spec.strip_prefix("SIG").unwrap_or(&spec);
+    names()
+        .iter()
+        .find(|(name, _)| *name == spec)
+        .map(|&(_, sig)| sig)
+}
+
+/// The name of signal `sig` without its `SIG` prefix.
+pub fn name(sig: c_int) -> Option<&'static str> {
+    names()
+        .iter()
+        .find(|&&(_, n)| n == sig)
+        .map(|&(name, _)| name)
+}
+
+const SIGTTIN: c_int = 21;
+const SIGTTOU: c_int = 22;
+
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+type Handler = extern "C" fn(c_int);
+
+extern "C" {
+    fn signal(signum: c_int, handler: usize) -> usize;
+    #[link_name = "kill"]
+    fn sys_kill(pid: c_int, sig: c_int) -> c_int;
+}
+
+extern "C" fn ignore(_: c_int) {}
+
+/// Keeps `SIGINT` from terminating the shell while leaving foreground children to receive it.
+///
+/// A caught signal is reset to its default action across `exec`, unlike an ignored one, so a
+/// no-op handler shields the shell without children inheriting the disposition.
+pub fn shield_from_interrupts() {
+    // SAFETY: the handler is async-signal-safe since it does nothing.
+    unsafe {
+        signal(SIGINT, ignore as Handler as usize);
+    }
+}
+
+/// Ignores the signals a shell receives while handing the terminal between process groups:
+/// Ctrl+Z meant for a job, and terminal access while it is in the background.
+pub fn ignore_job_control() {
+    // SAFETY: changing dispositions to `SIG_IGN` has no preconditions.
+    unsafe {
+        for sig in [SIGTSTP, SIGTTIN, SIGTTOU] {
+            signal(sig, SIG_IGN);
+        }
+    }
+}
+
+/// Restores the default dispositions the shell changes. Ignored signals survive `exec`, so
+/// this runs in each child between `fork` and `exec`, and must stay async-signal-safe. A
+/// copy of the shell running a builtin as a job calls it too, to be interrupted and
+/// stopped like any other.
+pub fn restore_defaults() {
+    // SAFETY: `signal` is async-signal-safe.
+    unsafe {
+        for sig in [SIGINT, SIGTSTP, SIGTTIN, SIGTTOU] {
+            signal(sig, SIG_DFL);
+        }
+    }
+}
+
+/// Sends `sig` to a process, or to a whole process group if `pid` is negative.
+pub fn kill(pid: i32, sig: c_int) -> io::Result<()> {
+    // SAFETY: `kill` has no memory-safety preconditions.
+    if unsafe { sys_kill(pid, sig) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals_by_name_or_number() {
+        assert_eq!(parse("TERM"), Some(15));
+        assert_eq!(parse("SIGKILL"), Some(9));
+        assert_eq!(parse("int"), Some(2));
+        assert_eq!(parse("1"), Some(1));
+        assert_eq!(parse("0"), Some(0));
+        assert_eq!(parse("FOO"), None);
+        assert_eq!(parse("99"), None);
+        assert_eq!(name(9), Some("KILL"));
+        assert_eq!(name(99), None);
+    }
+}
@@ -0,0 +1,1103 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::PathBuf,
+    process,
+};
+
+use crate::{
+    command::{strerror, working_dir, Output},
+    completion::{self, Executables},
+    history::{self, History},
+    jobs::{self, Job, JobState, Jobs, Stage},
+    list::{self, List},
+    pipeline::{pipe, Pipeline},
+    prompt, signal,
+    token::{heredoc_delimiters, tokenize, Expand},
+    tty::{self, Key, Keys, RawMode},
+};
+
+const PROMPT: &str = "$ ";
+const CONTINUATION_PROMPT: &str = "> ";
+
+pub struct Terminal {
+    pub history: History,
+    pub jobs: Jobs,
+    pub aliases: BTreeMap<String, String>,
+    pub executables: Executables,
+    /// Variables that haven't been exported. Exported ones live in the environment.
+    pub variables: BTreeMap<String, String>,
+    /// Where output goes instead of stdout in the copy of the shell running a command
+    /// substitution.
+    capture: Option<Output>,
+    /// Set in that copy, and in one running a builtin that shares a pipeline, which leave
+    /// saving the history to the shell they were forked from.
+    pub subshell: bool,
+    /// The process group of the job that a copy of the shell runs a list in the background
+    /// for. The commands it starts join the group and never take the terminal.
+    pub job_group: Option<i32>,
+    pub options: Options,
+    /// How many scripts `source` is in the middle of, which a command failing under `set -e`
+    /// stops instead of the shell.
+    sourcing: usize,
+    /// Set when `set -e` stops the script being sourced.
+    abort_script: bool,
+    /// The directories `pushd` left, with the latest last.
+    pub dir_stack: Vec<PathBuf>,
+    pub last_status: i32,
+    /// The exit status of each stage of the last foreground pipeline.
+    pipe_status: Vec<i32>,
+    /// Whether stdin is a terminal, which is when input goes through the line editor.
+    pub interactive: bool,
+    /// Set once the user has been warned that jobs are left, so that exiting again right
+    /// away goes through.
+    warned_of_jobs: bool,
+    /// Here-document bodies read ahead of the commands that use them, oldest first.
+    pub heredocs: VecDeque<String>,
+    /// The prompt shown before the line being edited.
+    prompt: String,
+    /// The line being edited.
+    input: String,
+    /// The cursor's position in `input`, in characters rather than bytes, so it always
+    /// falls on a character boundary; `byte_pos` converts it for indexing.
+    cursor_pos: usize,
+    /// The history entry being edited, where `history.len()` is the new line.
+    history_pos: usize,
+    /// Changes made to history entries while browsing, by position, with the new line at
+    /// `history.len()`. They only last until the line is entered, so the history itself keeps
+    /// the commands as they were run.
+    edits: BTreeMap<usize, String>,
+    /// The row the terminal's cursor is on below the first row of the prompt, for lines
+    /// long enough to wrap.
+    cursor_row: usize,
+    /// Set after a Tab that found several completions without being able to narrow them
+    /// down, so that pressing Tab again lists them.
+    tab_pending: bool,
+    /// The history entry whose last word Alt+. inserted and where that word starts, so that
+    /// pressing it again can swap in the last word of the entry before.
+    last_word: Option<(usize, usize)>,
+    /// What has been typed since reverse search started.
+    search_query: String,
+    /// The history entry reverse search currently shows.
+    search_match: Option<usize>,
+}
+
+/// Shell options toggled with `set` and `shopt`.
+#[derive(Default)]
+pub struct Options {
+    /// `-e`: stop at the first command that fails.
+    pub errexit: bool,
+    /// `-x`: print each command before running it.
+    pub xtrace: bool,
+    /// `-C`: keep `>` from overwriting files.
+    pub noclobber: bool,
+    /// `no_empty_cmd_completion`: ring the bell rather than list every command when Tab is
+    /// pressed on an empty line.
+    pub no_empty_cmd_completion: bool,
+}
+
+impl Options {
+    /// Turns the option with the one-letter `flag` on or off, returning false if there's
+    /// no such option.
+    pub fn set_flag(&mut self, flag: char, on: bool) -> bool {
+        match flag {
+            'e' => self.errexit = on,
+            'x' => self.xtrace = on,
+            'C' => self.noclobber = on,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Turns the option with the long `name` from `set -o` on or off, returning false if
+    /// there's no such option.
+    pub fn set_named(&mut self, name: &str, on: bool) -> bool {
+        match name {
+            "errexit" => self.set_flag('e', on),
+            "xtrace" => self.set_flag('x', on),
+            "noclobber" => self.set_flag('C', on),
+            _ => false,
+        }
+    }
+
+    /// The names `shopt` knows, in the order it lists them.
+    pub const SHOPT_NAMES: &'static [&'static str] = &["no_empty_cmd_completion"];
+
+    /// Whether the `shopt` option `name` is on, or `None` if there's no such option.
+    pub fn shopt(&self, name: &str) -> Option<bool> {
+        match name {
+            "no_empty_cmd_completion" => Some(self.no_empty_cmd_completion),
+            _ => None,
+        }
+    }
+
+    /// Turns the `shopt` option `name` on or off, returning false if there's no such option.
+    pub fn set_shopt(&mut self, name: &str, on: bool) -> bool {
+        match name {
+            "no_empty_cmd_completion" => self.no_empty_cmd_completion = on,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// What to do after a key press in the editor.
+enum Edit {
+    Continue,
+    Submit,
+    Eof,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        signal::shield_from_interrupts();
+        signal::ignore_job_control();
+        // An inherited `PWD` is only kept if it still names the current directory.
+        if let Ok(dir) = working_dir() {
+            env::set_var("PWD", dir);
+        }
+        let history = match env::var_os("HISTFILE") {
+            Some(path) => History::open(path, history::size(env::var("HISTSIZE").ok().as_deref()))
+                .unwrap_or_else(|e| {
+                    eprintln!("history: {}", strerror(&e));
+                    History::default()
+                }),
+            None => History::default(),
+        };
+        Terminal {
+            history,
+            jobs: Jobs::default(),
+            aliases: BTreeMap::new(),
+            executables: Executables::default(),
+            variables: BTreeMap::new(),
+            capture: None,
+            subshell: false,
+            job_group: None,
+            options: Options::default(),
+            sourcing: 0,
+            abort_script: false,
+            dir_stack: vec![],
+            last_status: 0,
+            pipe_status: vec![],
+            interactive: io::stdin().is_terminal(),
+            warned_of_jobs: false,
+            heredocs: VecDeque::new(),
+            prompt: String::new(),
+            input: String::new(),
+            cursor_pos: 0,
+            cursor_row: 0,
+            history_pos: 0,
+            edits: BTreeMap::new(),
+            tab_pending: false,
+            last_word: None,
+            search_query: String::new(),
+            search_match: None,
+        }
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        self.source_rc_file()?;
+        loop {
+            self.report_finished_jobs()?;
+            let prompt = self.prompt("PS1", PROMPT);
+            let Some(mut input) = self.read_line(&prompt)? else {
+                if self.holds_back_exit()? {
+                    continue;
+                }
+                self.exit(self.last_status);
+            };
+            let warned = self.warned_of_jobs;
+            while self.incomplete(&input) {
+                let prompt = self.prompt("PS2", CONTINUATION_PROMPT);
+                match self.read_line(&prompt)? {
+                    Some(line) => input.push_str(&line),
+                    // What there is runs anyway, to report what is missing.
+                    None => break,
+                }
+            }
+            match self.history.expand(&input) {
+                Ok(Some(expanded)) => {
+                    // The expanded command is echoed so that it's clear what runs.
+                    print!("{}", expanded.line);
+                    if expanded.print_only {
+                        self.add_history(&expanded.line);
+                        continue;
+                    }
+                    input = expanded.line;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    self.last_status = 1;
+                    continue;
+                }
+            }
+
+            self.add_history(&input);
+            self.read_heredocs(&input, &mut |term| {
+                term.read_line(&term.prompt("PS2", CONTINUATION_PROMPT))
+            })?;
+            if let Err(e) = self.run(&input) {
+                eprintln!("{}", e);
+            }
+            // Bodies are left over if an error stopped the line before reaching them.
+            self.heredocs.clear();
+            // Only an exit on the line right after the warning goes through.
+            if warned {
+                self.warned_of_jobs = false;
+            }
+        }
+    }
+
+    /// Warns that there are jobs left the first time the user tries to exit with some, as
+    /// bash does, returning whether to stay. Trying again straight after leaves anyway.
+    pub fn holds_back_exit(&mut self) -> Result<bool> {
+        if !self.interactive || self.subshell || self.warned_of_jobs {
+            return Ok(false);
+        }
+        self.jobs.poll()?;
+        let states: Vec<_> = self.jobs.list().iter().map(|job| job.state()).collect();
+        let kind = if states.contains(&JobState::Stopped) {
+            "stopped"
+        } else if states.contains(&JobState::Running) {
+            "running"
+        } else {
+            return Ok(false);
+        };
+        eprintln!("There are {} jobs.", kind);
+        self.warned_of_jobs = true;
+        Ok(true)
+    }
+
+    /// Records `command` in the history as `HISTCONTROL` and `HISTSIZE` say, which are looked
+    /// up each time so that changing them takes effect right away.
+    pub fn add_history(&mut self, command: &str) {
+        let control = self.lookup("HISTCONTROL").unwrap_or_default();
+        let size = self.history_size();
+        self.history.add(command, &control, size);
+    }
+
+    /// How many entries the history keeps.
+    pub fn history_size(&self) -> usize {
+        history::size(self.lookup("HISTSIZE").as_deref())
+    }
+
+    /// Runs the startup file named by `SHELLRC`, or `~/.shellrc` by default, if there is one.
+    fn source_rc_file(&mut self) -> Result<()> {
+        let path = match (env::var("SHELLRC"), env::var("HOME")) {
+            (Ok(path), _) => path,
+            (Err(_), Ok(home)) => format!("{}/.shellrc", home),
+            (Err(_), Err(_)) => return Ok(()),
+        };
+        if let Ok(script) = fs::read_to_string(&path) {
+            self.source(&path, &script, &mut io::stderr())?;
+        }
+        Ok(())
+    }
+
+    /// Runs each line of `script`, read from `path`, in the current shell and returns the
+    /// last status. A line that fails is reported to `err` with its number and the rest of
+    /// the script still runs.
+    pub fn source(&mut self, path: &str, script: &str, err: &mut dyn Write) -> Result<i32> {
+        self.last_status = 0;
+        self.sourcing += 1;
+        let mut lines = script.lines();
+        let mut number = 0;
+        while let Some(line) = lines.next() {
+            number += 1;
+            let start = number;
+            let mut line = line.to_string();
+            while self.incomplete(&line) {
+                let Some(next) = lines.next() else {
+                    break;
+                };
+                number += 1;
+                line.push('\n');
+                line.push_str(next);
+            }
+            self.read_heredocs(&line, &mut |_| {
+                number += 1;
+                Ok(lines.next().map(String::from))
+            })?;
+            if let Err(e) = self.run(&line) {
+                writeln!(err, "{}: line {}: {}", path, start, e)?;
+            }
+            self.heredocs.clear();
+            if std::mem::take(&mut self.abort_script) {
+                break;
+            }
+        }
+        self.sourcing -= 1;
+        Ok(self.last_status)
+    }
+
+    /// Expands the prompt in the variable `name`, which is done every time it is shown since
+    /// what it shows can change.
+    fn prompt(&self, name: &str, default: &str) -> String {
+        match self.lookup(name) {
+            Some(template) => prompt::expand(&template),
+            None => default.to_string(),
+        }
+    }
+
+    /// Whether `input` stops partway through a command, like inside quotes or after a `|`.
+    pub fn incomplete(&self, input: &str) -> bool {
+        list::parse(input, &mut self.heredocs.clone()).is_err_and(|e| e.incomplete)
+    }
+
+    /// Reads a line of input after showing `prompt`, through the editor if stdin is a
+    /// terminal. Returns `None` at the end of the input.
+    fn read_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        if self.interactive {
+            return self.read_input(prompt);
+        }
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut input = String::new();
+        Ok((io::stdin().read_line(&mut input)? > 0).then_some(input))
+    }
+
+    /// Reads the bodies of the here-documents `line` opens from the lines `next_line`
+    /// returns, and queues them for the lexer to pick up as it reaches each `<<`.
+    pub fn read_heredocs(
+        &mut self,
+        line: &str,
+        next_line: &mut dyn FnMut(&mut Terminal) -> Result<Option<String>>,
+    ) -> Result<()> {
+        for (delimiter, strip_tabs) in heredoc_delimiters(line) {
+            let mut body = String::new();
+            loop {
+                let Some(line) = next_line(self)? else {
+                    eprintln!(
+                        "warning: here-document delimited by end-of-file (wanted `{}')",
+                        delimiter
+                    );
+                    break;
+                };
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line
+                };
+                if line == delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            self.heredocs.push_back(body);
+        }
+        Ok(())
+    }
+
+    /// Reads a line with the interactive editor, returning `None` once the user ends the
+    /// input with Ctrl+D.
+    fn read_input(&mut self, prompt: &str) -> Result<Option<String>> {
+        let _raw = RawMode::enable()?;
+        self.prompt = prompt.to_string();
+        let mut keys = Keys::new();
+        self.input.clear();
+        self.cursor_pos = 0;
+        self.cursor_row = 0;
+        self.history_pos = self.history.len();
+        self.edits.clear();
+        self.redraw()?;
+
+        loop {
+            let Some(key) = keys.next()? else {
+                self.finish_line("")?;
+                return Ok(None);
+            };
+            match self.process_input(key, &mut keys)? {
+                Edit::Continue => {}
+                Edit::Submit => {
+                    self.finish_line("")?;
+                    let mut input = std::mem::take(&mut self.input);
+                    input.push('\n');
+                    return Ok(Some(input));
+                }
+                Edit::Eof => {
+                    self.finish_line("")?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Moves past the end of the line, however many rows it takes up, and ends it with
+    /// `marker` so that whatever is printed next starts on a fresh row.
+    fn finish_line(&mut self, marker: &str) -> io::Result<()> {
+        self.cursor_pos = self.input.chars().count();
+        self.redraw()?;
+        println!("{}", marker);
+        self.cursor_row = 0;
+        Ok(())
+    }
+
+    fn process_input(&mut self, key: Key, keys: &mut Keys) -> Result<Edit> {
+        let tab_pending = std::mem::take(&mut self.tab_pending);
+        let last_word = self.last_word.take();
+        match key {
+            Key::Enter => return Ok(Edit::Submit),
+            Key::Ctrl('d') if self.input.is_empty() => return Ok(Edit::Eof),
+            Key::Ctrl('c') => {
+                // The line is abandoned, like an interrupted command.
+                self.finish_line("^C")?;
+                self.input.clear();
+                self.cursor_pos = 0;
+                self.history_pos = self.history.len();
+                self.last_status = 130;
+            }
+            Key::Ctrl('r') => return self.search(keys, false),
+            Key::Ctrl('s') => return self.search(keys, true),
+            Key::Tab => self.complete(tab_pending, keys)?,
+            Key::Char(c) => {
+                let at = self.byte_pos(self.cursor_pos);
+                self.input.insert(at, c);
+                self.cursor_pos += 1;
+            }
+            Key::Backspace if self.cursor_pos > 0 => {
+                self.cursor_pos -= 1;
+                self.input.remove(self.byte_pos(self.cursor_pos));
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor_pos < self.input.chars().count() => {
+                self.input.remove(self.byte_pos(self.cursor_pos));
+            }
+            Key::Left if self.cursor_pos > 0 => self.cursor_pos -= 1,
+            Key::Right if self.cursor_pos < self.input.chars().count() => self.cursor_pos += 1,
+            Key::Alt('.') => self.insert_last_word(last_word)?,
+            Key::Alt('b') => self.cursor_pos = self.word_start(),
+            Key::Alt('f') => self.cursor_pos = self.word_end(),
+            Key::Ctrl('w') if self.cursor_pos > 0 => {
+                // Like readline's unix-word-rubout, the word runs back to whitespace.
+                let start = self.word_start();
+                let range = self.byte_pos(start)..self.byte_pos(self.cursor_pos);
+                self.input.replace_range(range, "");
+                self.cursor_pos = start;
+            }
+            Key::Ctrl('u') if self.cursor_pos > 0 => {
+                let at = self.byte_pos(self.cursor_pos);
+                self.input.replace_range(..at, "");
+                self.cursor_pos = 0;
+            }
+            Key::Ctrl('k') => {
+                let at = self.byte_pos(self.cursor_pos);
+                self.input.truncate(at);
+            }
+            Key::Ctrl('l') => {
+                print!("\x1b[H\x1b[2J");
+                self.cursor_row = 0;
+            }
+            Key::Home | Key::Ctrl('a') => self.cursor_pos = 0,
+            Key::End | Key::Ctrl('e') => self.cursor_pos = self.input.chars().count(),
+            Key::Up if self.history_pos > 0 => {
+                self.keep_edit();
+                self.history_pos -= 1;
+                self.show_history_entry();
+            }
+            Key::Down if self.history_pos < self.history.len() => {
+                self.keep_edit();
+                self.history_pos += 1;
+                self.show_history_entry();
+            }
+            _ => tty::bell()?,
+        }
+        self.redraw()?;
+        Ok(Edit::Continue)
+    }
+
+    /// Completes the word before the cursor. A single candidate is filled in, followed by
+    /// a space unless it is a directory; several are narrowed down to what they have in
+    /// common, and listed if that is nothing new and Tab is pressed a second time. A listing
+    /// too long to take in at a glance is only shown once the user answers `y`.
+    fn complete(&mut self, listing: bool, keys: &mut Keys) -> io::Result<()> {
+        let end = self.byte_pos(self.cursor_pos);
+        if self.options.no_empty_cmd_completion && self.input[..end].trim().is_empty() {
+            return tty::bell();
+        }
+        let completion = completion::complete(
+            &self.input[..end],
+            self.aliases.keys(),
+            &mut self.executables,
+        );
+        let start = completion.start;
+        let word = &self.input[start..end];
+        let mut replacement = match completion.candidates.as_slice() {
+            [] => return tty::bell(),
+            [only] if only.ends_with('/') => completion.quote(only, false),
+            [only] => format!("{} ", completion.quote(only, true)),
+            candidates => completion.quote(completion::common_prefix(candidates), false),
+        };
+        // Completing a word in the middle of the line steps over the space already after it
+        // rather than adding another.
+        let step_over = replacement.ends_with(' ') && self.input[end..].starts_with(' ');
+        if step_over {
+            replacement.pop();
+        }
+
+        // A builtin typed in another case is replaced even if nothing is added to it.
+        if replacement.len() >= word.len() && (replacement != word || step_over) {
+            self.input.replace_range(start..end, &replacement);
+            let after = start + replacement.len() + usize::from(step_over);
+            self.cursor_pos = self.input[..after].chars().count();
+        } else if listing {
+            let cursor_pos = self.cursor_pos;
+            self.finish_line("")?;
+            let names: Vec<_> = completion
+                .candidates
+                .iter()
+                .map(|candidate| {
+                    // Paths are listed by their last component, as in bash.
+                    let name = candidate.trim_end_matches('/');
+                    let name_start = name.rfind('/').map_or(0, |i| i + 1);
+                    &candidate[name_start..]
+                })
+                .collect();
+            let limit = self.lookup("COMPLETION_QUERY_ITEMS");
+            if completion::should_ask(names.len(), limit.as_deref())
+                && !confirm_listing(names.len(), keys)?
+            {
+                self.cursor_pos = cursor_pos;
+                return Ok(());
+            }
+            for row in completion::layout(&names, tty::columns()) {
+                println!("{}", row);
+            }
+            self.cursor_pos = cursor_pos;
+        } else {
+            self.tab_pending = true;
+            tty::bell()?;
+        }
+        Ok(())
+    }
+
+    /// Searches through the history as the query is typed, backwards from the end or
+    /// `forward` from the entry being edited. Ctrl+R moves on to older matches and Ctrl+S to
+    /// newer ones, Enter takes the match into the line, Escape goes back to the line as it
+    /// was, and Ctrl+C abandons the line altogether. Any other key takes the match and is
+    /// then handled as usual.
+    fn search(&mut self, keys: &mut Keys, mut forward: bool) -> Result<Edit> {
+        self.search_query.clear();
+        self.search_match = None;
+        let origin = self.history_pos;
+        let end = self.history.len();
+        let mut failed = false;
+        loop {
+            self.draw_search(forward, failed)?;
+            let Some(key) = keys.next()? else {
+                return Ok(Edit::Eof);
+            };
+            // Going back, the search looks before this entry; going forward, from it on.
+            let from = match key {
+                Key::Ctrl('r') => {
+                    forward = false;
+                    self.search_match.unwrap_or(end)
+                }
+                Key::Ctrl('s') => {
+                    forward = true;
+                    self.search_match.map_or(origin, |i| i + 1)
+                }
+                Key::Char(c) => {
+                    self.search_query.push(c);
+                    // The current match may still contain the longer query.
+                    match forward {
+                        true => self.search_match.unwrap_or(origin),
+                        false => self.search_match.map_or(end, |i| i + 1),
+                    }
+                }
+                Key::Backspace => {
+                    self.search_query.pop();
+                    if forward {
+                        origin
+                    } else {
+                        end
+                    }
+                }
+                Key::Esc => break,
+                Key::Ctrl('c') => return self.process_input(key, keys),
+                key => {
+                    self.accept_search();
+                    return match key {
+                        Key::Enter => {
+                            self.redraw()?;
+                            Ok(Edit::Continue)
+                        }
+                        key => self.process_input(key, keys),
+                    };
+                }
+            };
+            let found = match forward {
+                true => self.history.search_forward(&self.search_query, from),
+                false => self.history.search(&self.search_query, from),
+            };
+            match found {
+                Some(i) if !self.search_query.is_empty() => {
+                    self.search_match = Some(i);
+                    failed = false;
+                }
+                _ if self.search_query.is_empty() => {
+                    self.search_match = None;
+                    failed = false;
+                }
+                _ => {
+                    failed = true;
+                    tty::bell()?;
+                }
+            }
+        }
+        self.redraw()?;
+        Ok(Edit::Continue)
+    }
+
+    fn accept_search(&mut self) {
+        if let Some(entry) = self.search_match.and_then(|i| self.history.get(i)) {
+            self.input = entry.to_string();
+            self.cursor_pos = self.input.chars().count();
+            self.history_pos = self.history.len();
+        }
+    }
+
+    fn draw_search(&mut self, forward: bool, failed: bool) -> io::Result<()> {
+        let found = self.search_match.and_then(|i| self.history.get(i));
+        let line = format!(
+            "({}{}i-search)`{}': {}",
+            if failed { "failed " } else { "" },
+            if forward { "" } else { "reverse-" },
+            self.search_query,
+            found.unwrap_or_default()
+        );
+        self.draw(&line, tty::width(&line))
+    }
+
+    /// Remembers the line as the edited version of the entry at `history_pos`, unless it is
+    /// the entry as it was.
+    fn keep_edit(&mut self) {
+        match self.history.get(self.history_pos) {
+            Some(entry) if entry == self.input => {
+                self.edits.remove(&self.history_pos);
+            }
+            _ => {
+                self.edits.insert(self.history_pos, self.input.clone());
+            }
+        }
+    }
+
+    /// Replaces the line with the history entry at `history_pos`, as it was last edited if it
+    /// was, or the new line past the end.
+    fn show_history_entry(&mut self) {
+        self.input = match self.edits.get(&self.history_pos) {
+            Some(edit) => edit.clone(),
+            None => self
+                .history
+                .get(self.history_pos)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        self.cursor_pos = self.input.chars().count();
+    }
+
+    /// Rewrites the prompt and line, then moves the terminal's cursor back to `cursor_pos`.
+    fn redraw(&mut self) -> io::Result<()> {
+        let line = format!("{}{}", self.prompt, self.input);
+        let cursor =
+            tty::width(&self.prompt) + tty::width(&self.input[..self.byte_pos(self.cursor_pos)]);
+        self.draw(&line, cursor)
+    }
+
+    /// Replaces what was drawn last with `line`, which may wrap onto several rows, and puts
+    /// the terminal's cursor `cursor` columns into it.
+    fn draw(&mut self, line: &str, cursor: usize) -> io::Result<()> {
+        let columns = tty::columns();
+        let mut out = io::stdout().lock();
+        if self.cursor_row > 0 {
+            write!(out, "\x1b[{}A", self.cursor_row)?;
+        }
+        write!(out, "\r\x1b[J{}", line)?;
+
+        let len = tty::width(line);
+        let (end_row, end_column) = tty::position(len, columns);
+        // A line that exactly fills its last row leaves the terminal's cursor hanging at the
+        // edge rather than at the start of the next row, which would throw off the moves below.
+        if len > 0 && end_column == 0 {
+            write!(out, "\r\n")?;
+        }
+        let (row, column) = tty::position(cursor, columns);
+        if end_row > row {
+            write!(out, "\x1b[{}A", end_row - row)?;
+        }
+        write!(out, "\r")?;
+        if column > 0 {
+            write!(out, "\x1b[{}C", column)?;
+        }
+        self.cursor_row = row;
+        out.flush()
+    }
+
+    /// Inserts the last word of the previous command at the cursor, or after a press that
+    /// just did so, replaces that word with the last word of the command before.
+    fn insert_last_word(&mut self, previous: Option<(usize, usize)>) -> io::Result<()> {
+        let before = previous.map_or(self.history.len(), |(index, _)| index);
+        let found = (0..before).rev().find_map(|index| {
+            let word = self.history.get(index)?.split_whitespace().last()?;
+            Some((index, word.to_string()))
+        });
+        let Some((index, word)) = found else {
+            self.last_word = previous;
+            return tty::bell();
+        };
+        let start = match previous {
+            Some((_, start)) => {
+                let range = self.byte_pos(start)..self.byte_pos(self.cursor_pos);
+                self.input.replace_range(range, "");
+                start
+            }
+            None => self.cursor_pos,
+        };
+        self.input.insert_str(self.byte_pos(start), &word);
+        self.cursor_pos = start + word.chars().count();
+        self.last_word = Some((index, start));
+        Ok(())
+    }
+
+    /// Where the word before the cursor starts, a word being a run of anything but
+    /// whitespace.
+    fn word_start(&self) -> usize {
+        let chars: Vec<_> = self.input.chars().take(self.cursor_pos).collect();
+        let mut start = self.cursor_pos;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+
+    /// Where the word after the cursor ends.
+    fn word_end(&self) -> usize {
+        let rest: Vec<_> = self.input.chars().skip(self.cursor_pos).collect();
+        let mut end = 0;
+        while end < rest.len() && rest[end].is_whitespace() {
+            end += 1;
+        }
+        while end < rest.len() && !rest[end].is_whitespace() {
+            end += 1;
+        }
+        self.cursor_pos + end
+    }
+
+    /// The byte offset of the character at `pos` in the line.
+    fn byte_pos(&self, pos: usize) -> usize {
+        self.input
+            .char_indices()
+            .nth(pos)
+            .map_or(self.input.len(), |(i, _)| i)
+    }
+
+    /// Parses `input` into a list and runs it. A syntax error anywhere in `input` keeps all
+    /// of it from running, and any other error stops the rest of it; either is left for the
+    /// caller to report.
+    pub fn run(&mut self, input: &str) -> Result<()> {
+        let mut heredocs = std::mem::take(&mut self.heredocs);
+        let parsed = list::parse(input, &mut heredocs);
+        self.heredocs = heredocs;
+        match parsed {
+            Ok(Some(list)) => self.evaluate(list, true),
+            Ok(None) => Ok(()),
+            Err(e) => {
+                self.last_status = 2;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Runs `list`, skipping the right side of `&&` or `||` unless its left side succeeded
+    /// or failed respectively, which leaves the status untouched. Under `set -e` a pipeline
+    /// that fails exits the shell, or just stops the script being sourced, when it is
+    /// `checked`; those on the left of `&&` or `||` aren't.
+    fn evaluate(&mut self, list: List, checked: bool) -> Result<()> {
+        match list {
+            List::Pipeline(mut pipeline) => {
+                if let Some(list) = self.alias_list(&mut pipeline)? {
+                    return self.evaluate(list, checked);
+                }
+                let job = self.start_pipeline(pipeline, true)?;
+                self.last_status = self.foreground(job)?;
+                if self.options.errexit && checked && self.last_status != 0 {
+                    if self.sourcing == 0 {
+                        self.exit(self.last_status);
+                    }
+                    self.abort_script = true;
+                }
+            }
+            List::And(left, right) => {
+                self.evaluate(*left, false)?;
+                if self.last_status == 0 {
+                    self.evaluate(*right, checked)?;
+                }
+            }
+            List::Or(left, right) => {
+                self.evaluate(*left, false)?;
+                if self.last_status != 0 {
+                    self.evaluate(*right, checked)?;
+                }
+            }
+            List::Sequence(first, second) => {
+                self.evaluate(*first, checked)?;
+                if !self.abort_script {
+                    self.evaluate(*second, checked)?;
+                }
+            }
+            List::Background(list, command) => self.background(*list, &command)?,
+        }
+        Ok(())
+    }
+
+    /// Starts `list` as a job named `command` without waiting for it. A single pipeline is
+    /// started as it is, and anything more in a copy of the shell that runs it.
+    fn background(&mut self, list: List, command: &str) -> Result<()> {
+        let job = match list {
+            List::Pipeline(mut pipeline) => match self.alias_list(&mut pipeline)? {
+                Some(list) => return self.background(list, command),
+                None => self.start_pipeline(pipeline, false)?,
+            },
+            list => self.fork_list(list, command)?,
+        };
+        let job = self.jobs.add(job);
+        match job.pid() {
+            Some(pid) => println!("[{}] {}", job.id, pid),
+            None => println!("[{}]", job.id),
+        }
+        self.last_status = 0;
+        Ok(())
+    }
+
+    /// What `pipeline` stands for if its command is an alias, parsed as a list of its own
+    /// since an alias can hold several commands.
+    fn alias_list(&mut self, pipeline: &mut list::Pipeline) -> Result<Option<List>> {
+        if !pipeline.aliases {
+            return Ok(None);
+        }
+        let text = self.expand_alias(&pipeline.text);
+        if text == pipeline.text {
+            return Ok(None);
+        }
+        match list::parse(&text, &mut pipeline.heredocs) {
+            Ok(Some(mut list)) => {
+                // The alias was expanded as far as it goes already.
+                list.first_mut().aliases = false;
+                Ok(Some(list))
+            }
+            // An alias for nothing leaves nothing to run.
+            Ok(None) => Ok(Some(List::Pipeline(list::Pipeline {
+                text,
+                heredocs: VecDeque::new(),
+                aliases: false,
+            }))),
+            Err(e) => {
+                self.last_status = 2;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Expands `pipeline` and starts it as a job named by its text.
+    fn start_pipeline(&mut self, mut pipeline: list::Pipeline, foreground: bool) -> Result<Job> {
+        let tokens = match tokenize(&pipeline.text, self, &mut pipeline.heredocs) {
+            Ok((tokens, _)) => tokens,
+            Err(e) => {
+                self.last_status = 2;
+                return Err(e);
+            }
+        };
+        let stdout = match &self.capture {
+            Some(capture) => capture.try_clone()?,
+            None => Output::Stdout,
+        };
+        match Pipeline::from_tokens(tokens, stdout, self.options.noclobber) {
+            Ok(built) => built.spawn(self, pipeline.text.trim(), foreground),
+            Err(e) => {
+                self.last_status = 2;
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs `list` in a copy of the shell in a process group of its own, which is the job
+    /// named `command`, and exits with its status.
+    fn fork_list(&mut self, list: List, command: &str) -> Result<Job> {
+        io::stdout().flush()?;
+        let pid = jobs::fork()?;
+        if pid != 0 {
+            jobs::set_group(pid, pid);
+            let stages = vec![Stage::Running(pid as u32)];
+            return Ok(Job::new(command.to_string(), Some(pid), stages));
+        }
+
+        jobs::set_group(0, 0);
+        signal::restore_defaults();
+        self.subshell = true;
+        self.job_group = Some(jobs::current_group());
+        if let Err(e) = self.evaluate(list, true) {
+            eprintln!("{}", e);
+        }
+        self.exit(self.last_status);
+    }
+
+    /// Replaces an alias naming the command at the start of `input` with its definition.
+    /// The result is checked again so that aliases can build on each other, but an alias is
+    /// only expanded once to keep ones like `alias ls='ls -F'` from recursing forever.
+    fn expand_alias(&self, input: &str) -> String {
+        let mut input = input.to_string();
+        let mut expanded = vec![];
+        loop {
+            let command = input.trim_start();
+            let end = command
+                .find(|c: char| c.is_whitespace() || ";&|<>".contains(c))
+                .unwrap_or(command.len());
+            let name = &command[..end];
+            // A quoted or escaped command name is never an alias.
+            if name.contains(['\'', '"', '\\', '$']) || expanded.iter().any(|n| n == name) {
+                return input;
+            }
+            let Some(value) = self.aliases.get(name) else {
+                return input;
+            };
+            expanded.push(name.to_string());
+            input = format!("{}{}", value, &command[end..]);
+        }
+    }
+
+    /// Hands the terminal to `job` and waits for it to finish or stop, returning its exit
+    /// status. A stopped job joins the jobs table and reports `128 + SIGTSTP` like in bash.
+    pub fn foreground(&mut self, mut job: Job) -> Result<i32> {
+        // In a list running in the background the job stays there too.
+        let takes_terminal = self.job_group.is_none();
+        if let Some(group) = job.group.filter(|_| takes_terminal) {
+            jobs::set_foreground(group);
+        }
+        let waited = job.wait();
+        if takes_terminal {
+            jobs::set_foreground(jobs::current_group());
+        }
+        waited?;
+
+        match job.state() {
+            JobState::Done(status) => {
+                self.pipe_status = job.statuses().unwrap_or_default();
+                Ok(status)
+            }
+            JobState::Running | JobState::Stopped => {
+                let job = self.jobs.add(job);
+                println!();
+                println!("[{}]+  {:<24}{}", job.id, "Stopped", job.command);
+                Ok(128 + signal::SIGTSTP)
+            }
+        }
+    }
+
+    fn report_finished_jobs(&mut self) -> Result<()> {
+        for (job, status) in self.jobs.reap()? {
+            let state = match status {
+                0 => "Done".to_string(),
+                status => format!("Exit {}", status),
+            };
+            // The finished job is no longer tracked, so it always counts as the current one.
+            println!("[{}]+  {:<24}{}", job.id, state, job.command);
+        }
+        Ok(())
+    }
+
+    /// Saves the history and ends the shell with `status`.
+    pub fn exit(&mut self, status: i32) -> ! {
+        if self.subshell {
+            process::exit(status);
+        }
+        if let Err(e) = self.history.save() {
+            eprintln!("history: {}", strerror(&e));
+        }
+        process::exit(status);
+    }
+
+    /// Assigns a shell variable, or updates the environment if the variable is exported.
+    pub fn set_variable(&mut self, name: &str, value: &str) {
+        if env::var_os(name).is_some() {
+            env::set_var(name, value);
+        } else {
+            self.variables.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// Resolves a parameter for expansion, including special parameters like `$?`.
+    /// Array elements like `PIPESTATUS[1]` or `PIPESTATUS[@]` are supported too; any
+    /// other variable counts as an array of just its value.
+    pub fn lookup(&self, name: &str) -> Option<String> {
+        if let Some((array, index)) = name.strip_suffix(']').and_then(|n| n.split_once('[')) {
+            let values: Vec<String> = match array {
+                "PIPESTATUS" => self.pipe_status.iter().map(i32::to_string).collect(),
+                _ => self.lookup(array).into_iter().collect(),
+            };
+            return match index {
+                "@" | "*" => Some(values.join(" ")),
+                index => values.get(index.parse::<usize>().ok()?).cloned(),
+            };
+        }
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "PIPESTATUS" => self.pipe_status.first().map(i32::to_string),
+            _ => env::var(name)
+                .ok()
+                .or_else(|| self.variables.get(name).cloned()),
+        }
+    }
+}
+
+impl Expand for Terminal {
+    fn lookup(&self, name: &str) -> Option<String> {
+        Terminal::lookup(self, name)
+    }
+
+    /// Runs `command` in a forked copy of the shell with its output going to a pipe, so that
+    /// it can't change anything in this one, as with `cd` or `exit`. Its status becomes the
+    /// last status.
+    fn substitute(&mut self, command: &str) -> Result<String> {
+        let (mut read, write) = pipe()?;
+        io::stdout().flush()?;
+        let pid = jobs::fork()?;
+        if pid == 0 {
+            drop(read);
+            self.subshell = true;
+            self.capture = Some(Output::File(write));
+            if let Err(e) = self.run(command) {
+                eprintln!("{}", e);
+            }
+            self.exit(self.last_status);
+        }
+
+        // Reading ends once the child and everything it started have closed the pipe.
+        drop(write);
+        let mut output = vec![];
+        read.read_to_end(&mut output)?;
+        self.last_status = jobs::wait_for(pid)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+}
+
+/// Asks whether to list `count` completions and waits for `y` or `n`, as bash does. Space
+/// also says yes, and Escape, Ctrl+C or the end of the input say no.
+fn confirm_listing(count: usize, keys: &mut Keys) -> io::Result<bool> {
+    print!("Display all {} possibilities? (y or n)", count);
+    io::stdout().flush()?;
+    let answer = loop {
+        match keys.next()? {
+            Some(Key::Char('y' | 'Y' | ' ')) => break true,
+            Some(Key::Char('n' | 'N') | Key::Esc | Key::Ctrl('c')) | None => break false,
+            _ => {}
+        }
+    };
+    println!();
+    Ok(answer)
+}
@@ -0,0 +1,1095 @@
+use anyhow::{bail, Result};
+use std::{collections::VecDeque, iter::Peekable, str::Chars};
+use thiserror::Error;
+
+use crate::{brace, glob};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Arg(String),
+    Redirect {
+        kind: RedirectType,
+        path: String,
+        append: bool,
+        /// Set by `>|`, which overwrites the file even with `noclobber` on.
+        force: bool,
+    },
+    /// Points one stream at wherever another one currently goes, as in `2>&1`.
+    Duplicate {
+        kind: RedirectType,
+        target: RedirectType,
+    },
+    /// The text of a here-document or here-string, to be fed to the command's stdin.
+    HereDoc(String),
+    /// A redirection whose target, written as the text given, expanded to no word or
+    /// several, which fails the command it is on.
+    AmbiguousRedirect(String),
+    /// A `NAME=value` word before the command name.
+    Assignment {
+        name: String,
+        value: String,
+    },
+    Pipe,
+    Semicolon,
+    AndIf,
+    OrIf,
+    Background,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectType {
+    Stdout,
+    Stderr,
+    Stdin,
+    /// Stdout and stderr together, as in `&>`.
+    Both,
+}
+
+/// A command line that doesn't parse, with the column on its line, counting from 1, of the
+/// character where that became clear.
+#[derive(Debug, Error)]
+#[error("{message} (column {column})")]
+pub struct SyntaxError {
+    pub message: String,
+    pub column: usize,
+    /// Whether the input only ended too soon, as inside quotes or after a `|`, so that
+    /// reading more of it could still make a command.
+    pub incomplete: bool,
+}
+
+/// What expanding a word needs from the shell.
+pub trait Expand {
+    /// The value of a parameter, if it is set.
+    fn lookup(&self, name: &str) -> Option<String>;
+    /// Runs `command` and returns what it printed.
+    fn substitute(&mut self, command: &str) -> Result<String>;
+}
+
+/// Splits the first command of `input` into tokens, expanding `$` parameters and command
+/// substitutions through `expand`, and returns them with the input that follows. A command
+/// ends at a list operator like `;` or `&&`, which is kept as the last token; the rest is
+/// left unread so that it is expanded only once the commands before it have run. Each
+/// here-document takes the next body from `heredocs`, which the caller reads ahead of time.
+pub fn tokenize<'a>(
+    input: &'a str,
+    expand: &mut dyn Expand,
+    heredocs: &mut VecDeque<String>,
+) -> Result<(Vec<Token>, &'a str)> {
+    let mut lexer = Lexer {
+        input,
+        chars: input.chars().peekable(),
+        expand,
+        heredocs,
+        tokens: vec![],
+        word: String::new(),
+        pattern: String::new(),
+        glob: false,
+        in_word: false,
+        quoted: false,
+        assignment: false,
+        redirect: None,
+        target_start: 0,
+        ambiguous: false,
+        here: None,
+    };
+    lexer.run()?;
+    let unread: usize = lexer.chars.map(char::len_utf8).sum();
+    Ok((lexer.tokens, &input[input.len() - unread..]))
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<Chars<'a>>,
+    expand: &'a mut dyn Expand,
+    heredocs: &'a mut VecDeque<String>,
+    tokens: Vec<Token>,
+    word: String,
+    /// The current word as a pattern for brace expansion and globbing, with quoted braces,
+    /// commas and wildcards escaped.
+    pattern: String,
+    /// Whether the current word contains an unquoted wildcard.
+    glob: bool,
+    /// Set once any part of the current word has been seen, so `''` still yields an argument.
+    in_word: bool,
+    /// Whether the current word contains quoting, which rules it out as a file descriptor.
+    quoted: bool,
+    /// Whether the current word is an assignment, which is neither brace expanded nor globbed.
+    assignment: bool,
+    /// A redirection waiting for its target word, with whether it appends and whether it
+    /// is forced.
+    redirect: Option<(RedirectType, bool, bool)>,
+    /// The byte offset in `input` just past the operator of the pending redirection.
+    target_start: usize,
+    /// Set when an expansion in the target of the pending redirection split it apart.
+    ambiguous: bool,
+    /// A here-document or here-string waiting for its word.
+    here: Option<Here>,
+}
+
+#[derive(Clone, Copy)]
+enum Here {
+    /// `<<`, whose word is the delimiter of a body read ahead of time.
+    Document,
+    /// `<<<`, whose word is the text itself.
+    String,
+}
+
+impl Lexer<'_> {
+    fn run(&mut self) -> Result<()> {
+        while let Some(ch) = self.chars.next() {
+            match ch {
+                '\'' => {
+                    self.in_word = true;
+                    self.quoted = true;
+                    self.single_quoted()?;
+                }
+                '"' => {
+                    self.in_word = true;
+                    self.quoted = true;
+                    self.double_quoted()?;
+                }
+                '\\' => self.escaped()?,
+                '$' if self.chars.peek() == Some(&'(') => {
+                    let output = self.substitution(false)?;
+                    self.push_fields(&output)?;
+                }
+                '`' => {
+                    let output = self.substitution(true)?;
+                    self.push_fields(&output)?;
+                }
+                '$' => {
+                    let value = self.parameter()?;
+                    self.push_fields(&value)?;
+                }
+                // An assignment's value is expanded like a word of its own.
+                '~' if !self.in_word || self.assignment && self.word.ends_with('=') => self.tilde(),
+                // A comment runs to the end of the line.
+                '#' if !self.in_word => while self.chars.next_if(|c| *c != '\n').is_some() {},
+                '>' | '<' => self.redirect(ch)?,
+                '|' if self.next_if_eq('|') => return self.end_command(Token::OrIf, "||"),
+                '&' if self.next_if_eq('&') => return self.end_command(Token::AndIf, "&&"),
+                '&' if self.next_if_eq('>') => self.redirect_both()?,
+                '&' => return self.end_command(Token::Background, "&"),
+                '|' => {
+                    self.finish_word()?;
+                    if self.redirect_pending() || self.stage_empty() {
+                        return Err(self.syntax_error("syntax error near unexpected token `|'"));
+                    }
+                    self.tokens.push(Token::Pipe);
+                }
+                ';' => return self.end_command(Token::Semicolon, ";"),
+                c if c.is_whitespace() => self.finish_word()?,
+                c => {
+                    if c == '=' && !self.assignment && !self.quoted && is_name(&self.word) {
+                        self.assignment = !self.redirect_pending() && self.before_command();
+                    }
+                    self.in_word = true;
+                    self.push(c, false);
+                }
+            }
+        }
+
+        self.finish_word()?;
+        if self.redirect_pending() {
+            return Err(self.syntax_error("syntax error: expected filename after redirection"));
+        }
+        if self.tokens.last() == Some(&Token::Pipe) {
+            let message = "syntax error: unexpected end of input after `|'";
+            return Err(self.incomplete(message, self.column()));
+        }
+        Ok(())
+    }
+
+    /// The byte offset in `input` of the next character to read.
+    fn offset(&self) -> usize {
+        let unread: usize = self.chars.clone().map(char::len_utf8).sum();
+        self.input.len() - unread
+    }
+
+    /// The column of the character read last.
+    fn column(&self) -> usize {
+        let read = &self.input[..self.offset()];
+        // An error at the end of a line is reported at the last character on it.
+        let read = read.strip_suffix('\n').unwrap_or(read);
+        read.chars().rev().take_while(|&c| c != '\n').count()
+    }
+
+    fn syntax_error(&self, message: &str) -> anyhow::Error {
+        SyntaxError {
+            message: message.to_string(),
+            column: self.column(),
+            incomplete: false,
+        }
+        .into()
+    }
+
+    /// A syntax error for input that ends before the command does.
+    fn incomplete(&self, message: &str, column: usize) -> anyhow::Error {
+        SyntaxError {
+            message: message.to_string(),
+            column,
+            incomplete: true,
+        }
+        .into()
+    }
+
+    fn redirect_pending(&self) -> bool {
+        self.redirect.is_some() || self.here.is_some()
+    }
+
+    /// Whether the command name of the current pipeline stage is still to come.
+    fn before_command(&self) -> bool {
+        !self
+            .tokens
+            .iter()
+            .rev()
+            .take_while(|token| **token != Token::Pipe)
+            .any(|token| matches!(token, Token::Arg(_)))
+    }
+
+    /// Whether nothing has been seen of the current pipeline stage yet.
+    fn stage_empty(&self) -> bool {
+        matches!(self.tokens.last(), None | Some(Token::Pipe))
+    }
+
+    fn next_if_eq(&mut self, c: char) -> bool {
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    /// Ends the command at a list operator, leaving the rest of the input unread.
+    fn end_command(&mut self, op: Token, text: &str) -> Result<()> {
+        self.finish_word()?;
+        if self.redirect_pending() || self.stage_empty() {
+            let message = format!("syntax error near unexpected token `{}'", text);
+            return Err(self.syntax_error(&message));
+        }
+        // The command after `&&` or `||` may still be on its way on the next line.
+        if matches!(op, Token::AndIf | Token::OrIf) && self.chars.clone().all(char::is_whitespace) {
+            let message = "syntax error: unexpected end of input";
+            return Err(self.incomplete(message, self.column()));
+        }
+        self.tokens.push(op);
+        Ok(())
+    }
+
+    fn single_quoted(&mut self) -> Result<()> {
+        let start = self.column();
+        while let Some(ch) = self.chars.next() {
+            if ch == '\'' {
+                return Ok(());
+            }
+            self.push(ch, true);
+        }
+        Err(self.incomplete("unexpected EOF while looking for matching `''", start))
+    }
+
+    /// Outside quotes a backslash makes the next character literal, and a backslash before
+    /// a newline joins the lines. A backslash that ends the input has nothing to escape or
+    /// join with, so it is reported as an error rather than silently dropped.
+    fn escaped(&mut self) -> Result<()> {
+        match self.chars.next() {
+            Some('\n') if self.chars.peek().is_some() => Ok(()),
+            Some('\n') | None => Err(self.incomplete("unexpected EOF after `\\'", self.column())),
+            Some(escaped) => {
+                self.in_word = true;
+                self.quoted = true;
+                self.push(escaped, true);
+                Ok(())
+            }
+        }
+    }
+
+    /// Inside double quotes a backslash only escapes `$`, `` ` ``, `"` and `\\`; before a
+    /// newline it joins the lines, and before anything else it is kept literally.
+    fn double_quoted(&mut self) -> Result<()> {
+        let start = self.column();
+        while let Some(ch) = self.chars.next() {
+            match ch {
+                '"' => return Ok(()),
+                '$' if self.chars.peek() == Some(&'(') => {
+                    let output = self.substitution(false)?;
+                    self.push_str(&output, true);
+                }
+                '`' => {
+                    let output = self.substitution(true)?;
+                    self.push_str(&output, true);
+                }
+                '$' => {
+                    let value = self.parameter()?;
+                    self.push_str(&value, true);
+                }
+                '\\' => match self.chars.next() {
+                    Some(escaped @ ('$' | '`' | '"' | '\\')) => self.push(escaped, true),
+                    Some('\n') => {}
+                    Some(other) => {
+                        self.push('\\', true);
+                        self.push(other, true);
+                    }
+                    None => break,
+                },
+                c => self.push(c, true),
+            }
+        }
+        Err(self.incomplete("unexpected EOF while looking for matching `\"'", start))
+    }
+
+    /// Expands a `~` or `~user` prefix at the start of a word. It stays literal when the user
+    /// is unknown or the prefix runs into anything but a `/` or the end of the word.
+    fn tilde(&mut self) {
+        let mut user = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        {
+            user.push(c);
+        }
+        let ends_prefix = match self.chars.peek() {
+            Some(&c) => c.is_whitespace() || matches!(c, '/' | '|' | '<' | '>' | ';' | '&'),
+            None => true,
+        };
+
+        let home = match ends_prefix {
+            false => None,
+            true if user.is_empty() => self.expand.lookup("HOME"),
+            true => user_home(&user),
+        };
+        self.in_word = true;
+        match home {
+            Some(home) => {
+                self.quoted = true;
+                self.push_str(&home, true);
+            }
+            None => {
+                self.push('~', false);
+                self.push_str(&user, false);
+            }
+        }
+    }
+
+    fn parameter(&mut self) -> Result<String> {
+        parameter(&mut self.chars, &|name| self.expand.lookup(name))
+    }
+
+    /// Runs the command of a `$(...)` or `` `...` `` substitution, whose opening has just
+    /// been read, and returns what it printed without the trailing newlines.
+    fn substitution(&mut self, backquoted: bool) -> Result<String> {
+        let start = self.column();
+        let (command, close) = if backquoted {
+            (backquoted_command(&mut self.chars), '`')
+        } else {
+            self.chars.next();
+            (parenthesized_command(&mut self.chars), ')')
+        };
+        let Some(command) = command else {
+            let message = format!("unexpected EOF while looking for matching `{}'", close);
+            return Err(self.incomplete(&message, start));
+        };
+        let output = self.expand.substitute(&command)?;
+        Ok(output.trim_end_matches('\n').to_string())
+    }
+
+    /// Starts a redirection, taking an unquoted file descriptor directly before `op` as its
+    /// source.
+    fn redirect(&mut self, op: char) -> Result<()> {
+        let fd = match self.word.as_str() {
+            "0" | "1" | "2" if !self.quoted => self.word.parse::<u8>().ok(),
+            _ => None,
+        };
+        if fd.is_none() {
+            self.finish_word()?;
+        }
+        self.word.clear();
+        self.pattern.clear();
+        self.glob = false;
+        self.in_word = false;
+        self.quoted = false;
+
+        if self.redirect_pending() {
+            let message = format!("syntax error near unexpected token `{}'", op);
+            return Err(self.syntax_error(&message));
+        }
+        if op == '<' && self.next_if_eq('<') {
+            if fd.is_some_and(|fd| fd != 0) {
+                bail!("{}<<: unsupported redirection", fd.unwrap_or_default());
+            }
+            if self.next_if_eq('<') {
+                self.here = Some(Here::String);
+            } else {
+                // The body was read along with the line; only the delimiter is left to skip.
+                self.next_if_eq('-');
+                self.here = Some(Here::Document);
+            }
+            return Ok(());
+        }
+        let kind = match (op, fd) {
+            ('<', None | Some(0)) => RedirectType::Stdin,
+            ('>', None | Some(1)) => RedirectType::Stdout,
+            ('>', Some(2)) => RedirectType::Stderr,
+            (op, Some(fd)) => bail!("{}{}: unsupported redirection", fd, op),
+            _ => unreachable!(),
+        };
+        if op == '>' && self.next_if_eq('&') {
+            let target = match self.chars.next() {
+                Some('1') => RedirectType::Stdout,
+                Some('2') => RedirectType::Stderr,
+                _ => {
+                    let message = "syntax error: expected file descriptor after `>&'";
+                    return Err(self.syntax_error(message));
+                }
+            };
+            self.tokens.push(Token::Duplicate { kind, target });
+            return Ok(());
+        }
+        let append = op == '>' && self.next_if_eq('>');
+        let force = op == '>' && !append && self.next_if_eq('|');
+        self.redirect = Some((kind, append, force));
+        self.target_start = self.offset();
+        Ok(())
+    }
+
+    /// Starts an `&>` or `&>>` redirection of both stdout and stderr.
+    fn redirect_both(&mut self) -> Result<()> {
+        self.finish_word()?;
+        if self.redirect_pending() {
+            return Err(self.syntax_error("syntax error near unexpected token `&>'"));
+        }
+        let append = self.next_if_eq('>');
+        self.redirect = Some((RedirectType::Both, append, false));
+        self.target_start = self.offset();
+        Ok(())
+    }
+
+    /// Appends a character to the current word. Braces and wildcards are only live if it is
+    /// unquoted.
+    fn push(&mut self, c: char, quoted: bool) {
+        self.word.push(c);
+        if matches!(c, '{' | '}' | ',') && quoted {
+            self.pattern.push('\\');
+        } else if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            if quoted {
+                self.pattern.push('\\');
+            } else {
+                self.glob |= c != ']' && c != '\\';
+            }
+        }
+        self.pattern.push(c);
+    }
+
+    fn push_str(&mut self, s: &str, quoted: bool) {
+        for c in s.chars() {
+            self.push(c, quoted);
+        }
+    }
+
+    /// Appends the unquoted value of an expansion, which the characters in `IFS` split into
+    /// separate words everywhere but in an assignment. Runs of whitespace in `IFS` separate
+    /// words without producing empty ones, as the ends of the value do, while each other
+    /// character in it ends a word, even an empty one. An expansion to nothing doesn't
+    /// produce an argument on its own. Wildcards in the value are live, but braces were never
+    /// part of the command line to be expanded. A here-string is never split, and the target
+    /// of a redirection that would be is marked ambiguous instead.
+    fn push_fields(&mut self, value: &str) -> Result<()> {
+        if let Some(Here::String) = self.here {
+            self.in_word = true;
+            self.quoted = true;
+            self.push_str(value, true);
+            return Ok(());
+        }
+        // Even an expansion to nothing is the target, which is then missing.
+        self.in_word |= self.redirect.is_some();
+        let ifs = match self.assignment {
+            true => String::new(),
+            false => self
+                .expand
+                .lookup("IFS")
+                .unwrap_or_else(|| " \t\n".to_string()),
+        };
+        let mut chars = value.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !ifs.contains(c) {
+                self.in_word = true;
+                self.quoted = true;
+                self.push(c, matches!(c, '{' | '}' | ','));
+                continue;
+            }
+            let mut delimited = !c.is_whitespace();
+            while let Some(&next) = chars.peek() {
+                if !ifs.contains(next) || !next.is_whitespace() && delimited {
+                    break;
+                }
+                delimited |= !next.is_whitespace();
+                chars.next();
+            }
+            self.in_word |= delimited;
+            if self.redirect.is_some() {
+                self.ambiguous |= !self.word.is_empty() && chars.peek().is_some();
+                continue;
+            }
+            self.finish_word()?;
+        }
+        Ok(())
+    }
+
+    fn finish_word(&mut self) -> Result<()> {
+        if !self.in_word {
+            return Ok(());
+        }
+        let word = std::mem::take(&mut self.word);
+        let pattern = std::mem::take(&mut self.pattern);
+        let glob = std::mem::take(&mut self.glob);
+        let quoted = std::mem::take(&mut self.quoted);
+        let assignment = std::mem::take(&mut self.assignment);
+        self.in_word = false;
+
+        match self.here.take() {
+            Some(Here::Document) => {
+                let body = self.heredocs.pop_front().unwrap_or_default();
+                // Quoting any part of the delimiter keeps the body literal.
+                let body = match quoted {
+                    true => body,
+                    false => expand_heredoc(&body, self.expand)?,
+                };
+                self.tokens.push(Token::HereDoc(body));
+                return Ok(());
+            }
+            Some(Here::String) => {
+                self.tokens.push(Token::HereDoc(word + "\n"));
+                return Ok(());
+            }
+            None => {}
+        }
+
+        if let Some((kind, append, force)) = self.redirect.take() {
+            if std::mem::take(&mut self.ambiguous) || word.is_empty() && !quoted {
+                let text = self.input[self.target_start..self.offset()].trim_start();
+                let end = text
+                    .find(|c: char| c.is_whitespace() || ";&|<>".contains(c))
+                    .unwrap_or(text.len());
+                self.tokens
+                    .push(Token::AmbiguousRedirect(text[..end].to_string()));
+                return Ok(());
+            }
+            self.tokens.push(Token::Redirect {
+                kind,
+                path: word,
+                append,
+                force,
+            });
+            return Ok(());
+        }
+        if assignment {
+            let (name, value) = word.split_once('=').unwrap_or_default();
+            self.tokens.push(Token::Assignment {
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+            return Ok(());
+        }
+
+        // Braces are expanded first, and then each of the words they make is globbed.
+        let patterns = brace::expand(&pattern);
+        let words = match patterns == [pattern.as_str()] {
+            true => vec![(word, pattern)],
+            false => patterns
+                .into_iter()
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| (glob::unescape(&pattern), pattern))
+                .collect(),
+        };
+        for (word, pattern) in words {
+            let matches = match glob {
+                true => glob::expand(&pattern),
+                false => vec![],
+            };
+            // A pattern that matches nothing is passed on as is.
+            match matches.is_empty() {
+                true => self.tokens.push(Token::Arg(word)),
+                false => self.tokens.extend(matches.into_iter().map(Token::Arg)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Expands the parameter following a `$`, which is kept literally if no name follows.
+fn parameter(
+    chars: &mut Peekable<Chars>,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let name = match chars.peek() {
+        Some('?') => {
+            chars.next();
+            "?".to_string()
+        }
+        Some('{') => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => bail!("unexpected EOF while looking for matching `}}'"),
+                }
+            }
+            if name != "?" && !is_name(&name) && !is_element(&name) {
+                bail!("${{{}}}: bad substitution", name);
+            }
+            name
+        }
+        Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                name.push(c);
+            }
+            name
+        }
+        _ => return Ok("$".to_string()),
+    };
+    Ok(lookup(&name).unwrap_or_default())
+}
+
+/// Reads the command of a `$(...)` substitution up to its closing parenthesis, past any
+/// that are quoted or nested. Returns `None` if it isn't closed.
+fn parenthesized_command(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut command = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                command.push(c);
+                command.push(chars.next()?);
+                continue;
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => return Some(command),
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+        command.push(c);
+    }
+    None
+}
+
+/// Reads the command of a `` `...` `` substitution up to the closing backquote. A backslash
+/// only escapes `$`, `` ` `` and `\\` in it. Returns `None` if it isn't closed.
+fn backquoted_command(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut command = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => return Some(command),
+            '\\' => match chars.next()? {
+                escaped @ ('$' | '`' | '\\') => command.push(escaped),
+                other => {
+                    command.push('\\');
+                    command.push(other);
+                }
+            },
+            c => command.push(c),
+        }
+    }
+    None
+}
+
+/// Expands parameters and command substitutions in the body of a here-document whose
+/// delimiter isn't quoted. As in double quotes, a backslash only escapes `$`, `` ` ``, `\\`
+/// and a newline.
+fn expand_heredoc(body: &str, expand: &mut dyn Expand) -> Result<String> {
+    let mut expanded = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        let command = match c {
+            '$' if chars.next_if_eq(&'(').is_some() => Some(parenthesized_command(&mut chars)),
+            '`' => Some(backquoted_command(&mut chars)),
+            _ => None,
+        };
+        if let Some(command) = command {
+            let Some(command) = command else {
+                bail!("unexpected EOF while looking for the end of a command substitution");
+            };
+            expanded.push_str(expand.substitute(&command)?.trim_end_matches('\n'));
+            continue;
+        }
+        match c {
+            '$' => expanded.push_str(&parameter(&mut chars, &|name| expand.lookup(name))?),
+            '\\' => match chars.next() {
+                Some(escaped @ ('$' | '`' | '\\')) => expanded.push(escaped),
+                Some('\n') => {}
+                Some(other) => {
+                    expanded.push('\\');
+                    expanded.push(other);
+                }
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    Ok(expanded)
+}
+
+/// Finds the here-documents `line` opens, in order, returning each one's delimiter and
+/// whether it was opened with `<<-` to strip leading tabs from the body.
+pub fn heredoc_delimiters(line: &str) -> Vec<(String, bool)> {
+    let mut delimiters = vec![];
+    let mut quote = None;
+    let mut word_start = true;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let at_word_start = std::mem::replace(&mut word_start, c.is_whitespace());
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '#') if at_word_start => break,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                chars.next();
+            }
+            (None, '<') if chars.next_if_eq(&'<').is_some() => {
+                if chars.next_if_eq(&'<').is_some() {
+                    continue;
+                }
+                let strip_tabs = chars.next_if_eq(&'-').is_some();
+                while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+                let mut delimiter = String::new();
+                let mut word_quote = None;
+                // Only an empty delimiter that is quoted, as in `<<''`, counts.
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    match (word_quote, c) {
+                        (Some(q), c) if c == q => word_quote = None,
+                        (Some(_), c) => delimiter.push(c),
+                        (None, '\'' | '"') => {
+                            word_quote = Some(c);
+                            quoted = true;
+                        }
+                        (None, '\\') => {
+                            chars.next();
+                            delimiter.extend(chars.peek());
+                        }
+                        (None, c) if c.is_whitespace() || ";&|<>()".contains(c) => break,
+                        (None, c) => delimiter.push(c),
+                    }
+                    chars.next();
+                }
+                if !delimiter.is_empty() || quoted {
+                    delimiters.push((delimiter, strip_tabs));
+                }
+            }
+            _ => {}
+        }
+    }
+    delimiters
+}
+
+/// Whether `name` is a valid shell variable name.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `name` refers to an array element, as in `PIPESTATUS[0]` or `PIPESTATUS[@]`.
+fn is_element(name: &str) -> bool {
+    let Some((array, index)) = name.strip_suffix(']').and_then(|n| n.split_once('[')) else {
+        return false;
+    };
+    is_name(array)
+        && (matches!(index, "@" | "*")
+            || !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Looks up a user's home directory in the password database.
+fn user_home(user: &str) -> Option<String> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<_> = line.split(':').collect();
+        (fields.len() >= 6 && fields[0] == user).then(|| fields[5].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands `$NAME` to the value given for it, and a command substitution to the
+    /// command in angle brackets.
+    struct Values(&'static [(&'static str, &'static str)]);
+
+    impl Expand for Values {
+        fn lookup(&self, name: &str) -> Option<String> {
+            self.0
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| value.to_string())
+        }
+
+        fn substitute(&mut self, command: &str) -> Result<String> {
+            Ok(format!("<{}>\n", command))
+        }
+    }
+
+    fn tokens(input: &str) -> Vec<Token> {
+        tokens_with(&[("HOME", "/home/me"), ("F", "a  b")], input)
+    }
+
+    fn tokens_with(values: &'static [(&'static str, &'static str)], input: &str) -> Vec<Token> {
+        tokenize(input, &mut Values(values), &mut VecDeque::new())
+            .unwrap()
+            .0
+    }
+
+    fn words(input: &str) -> Vec<String> {
+        words_with(&[("HOME", "/home/me"), ("F", "a  b")], input)
+    }
+
+    fn words_with(values: &'static [(&'static str, &'static str)], input: &str) -> Vec<String> {
+        tokens_with(values, input)
+            .into_iter()
+            .map(|token| match token {
+                Token::Arg(word) => word,
+                other => panic!("{:?} isn't a word", other),
+            })
+            .collect()
+    }
+
+    fn syntax_error(input: &str) -> SyntaxError {
+        tokenize(input, &mut Values(&[]), &mut VecDeque::new())
+            .unwrap_err()
+            .downcast()
+            .unwrap()
+    }
+
+    #[test]
+    fn single_quotes() {
+        assert_eq!(words("echo ''"), ["echo", ""]);
+        assert_eq!(words("echo a'b c'd"), ["echo", "ab cd"]);
+        assert_eq!(
+            words("echo 'one' 'two  three'"),
+            ["echo", "one", "two  three"]
+        );
+        assert_eq!(
+            words("echo 'a\\b' '$HOME' '\"'"),
+            ["echo", "a\\b", "$HOME", "\""]
+        );
+        assert_eq!(words("echo 'a''b'"), ["echo", "ab"]);
+    }
+
+    #[test]
+    fn double_quotes() {
+        assert_eq!(words("echo \"\""), ["echo", ""]);
+        assert_eq!(words("echo \"x $F y\""), ["echo", "x a  b y"]);
+        assert_eq!(
+            words("echo \"\\$F\" \"a\\\"b\" \"\\\\\""),
+            ["echo", "$F", "a\"b", "\\"]
+        );
+        assert_eq!(words("echo \"\\q\" \"'a'\""), ["echo", "\\q", "'a'"]);
+        assert_eq!(words("echo \"a\"b'c'\"d e\""), ["echo", "abcd e"]);
+        assert_eq!(words("echo \"$(true)\"x"), ["echo", "<true>x"]);
+    }
+
+    #[test]
+    fn backslashes() {
+        assert_eq!(words(r"echo \$F \\ \a\b"), ["echo", "$F", "\\", "ab"]);
+        assert_eq!(words(r"echo a\ \ b"), ["echo", "a  b"]);
+        assert_eq!(words(r"echo \> out \|"), ["echo", ">", "out", "|"]);
+        assert_eq!(words("echo a\\\nb c"), ["echo", "ab", "c"]);
+        assert!(syntax_error("echo a\\").incomplete);
+        assert!(syntax_error("echo a\\\n").incomplete);
+    }
+
+    #[test]
+    fn tildes() {
+        assert_eq!(words("ls ~ ~/file"), ["ls", "/home/me", "/home/me/file"]);
+        assert_eq!(
+            words("ls ~nonexistent a~b '~' \"~\""),
+            ["ls", "~nonexistent", "a~b", "~", "~"]
+        );
+    }
+
+    #[test]
+    fn appending_redirections() {
+        let redirect = |kind, append| Token::Redirect {
+            kind,
+            path: "f".into(),
+            append,
+            force: false,
+        };
+        assert_eq!(
+            tokens("a >f >>f 2>f 2>>f &>f &>>f"),
+            [
+                Token::Arg("a".into()),
+                redirect(RedirectType::Stdout, false),
+                redirect(RedirectType::Stdout, true),
+                redirect(RedirectType::Stderr, false),
+                redirect(RedirectType::Stderr, true),
+                redirect(RedirectType::Both, false),
+                redirect(RedirectType::Both, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(words("# echo no"), Vec::<String>::new());
+        assert_eq!(words("echo hi # greeting | cat"), ["echo", "hi"]);
+        assert_eq!(words("echo hi#not"), ["echo", "hi#not"]);
+        assert_eq!(words("echo '#' \"#\" \\#x"), ["echo", "#", "#", "#x"]);
+    }
+
+    #[test]
+    fn assignments_come_before_the_command() {
+        let assignment = |name: &str, value: &str| Token::Assignment {
+            name: name.into(),
+            value: value.into(),
+        };
+        assert_eq!(
+            tokens("A=1 B='x y' cmd C=2"),
+            [
+                assignment("A", "1"),
+                assignment("B", "x y"),
+                Token::Arg("cmd".into()),
+                Token::Arg("C=2".into()),
+            ]
+        );
+        assert_eq!(tokens("A=$HOME"), [assignment("A", "/home/me")]);
+        assert_eq!(tokens("'A'=1"), [Token::Arg("A=1".into())]);
+        assert_eq!(tokens("1A=1"), [Token::Arg("1A=1".into())]);
+    }
+
+    #[test]
+    fn syntax_errors_say_where() {
+        let error = syntax_error("| echo");
+        assert_eq!(error.message, "syntax error near unexpected token `|'");
+        assert_eq!((error.column, error.incomplete), (1, false));
+        let error = syntax_error("echo a | | b");
+        assert_eq!(error.message, "syntax error near unexpected token `|'");
+        assert_eq!(error.column, 10);
+        let error = syntax_error("echo 'unterminated");
+        assert_eq!(
+            error.message,
+            "unexpected EOF while looking for matching `''"
+        );
+        assert_eq!((error.column, error.incomplete), (6, true));
+        assert_eq!(
+            error.to_string(),
+            "unexpected EOF while looking for matching `'' (column 6)"
+        );
+    }
+
+    #[test]
+    fn empty_pipeline_stages() {
+        for (input, token, column) in [
+            ("| echo", "|", 1),
+            ("  | echo", "|", 3),
+            ("echo a | | cat", "|", 10),
+            ("echo a | ; echo b", ";", 10),
+            ("echo a | && echo b", "&&", 11),
+        ] {
+            let error = syntax_error(input);
+            let message = format!("syntax error near unexpected token `{}'", token);
+            assert_eq!(error.message, message, "{:?}", input);
+            assert_eq!(
+                (error.column, error.incomplete),
+                (column, false),
+                "{:?}",
+                input
+            );
+        }
+        let error = syntax_error("echo a |");
+        assert_eq!(
+            error.message,
+            "syntax error: unexpected end of input after `|'"
+        );
+        assert_eq!((error.column, error.incomplete), (8, true));
+    }
+
+    #[test]
+    fn redirections_need_a_target() {
+        for (input, column) in [
+            ("echo >", 6),
+            ("echo >>", 7),
+            ("echo 2>", 7),
+            ("echo 2>>", 8),
+            ("echo &>", 7),
+            ("echo <", 6),
+        ] {
+            let error = syntax_error(input);
+            assert_eq!(
+                (error.message.as_str(), error.column),
+                ("syntax error: expected filename after redirection", column),
+                "{:?}",
+                input
+            );
+        }
+        let error = syntax_error("echo > ;");
+        assert_eq!(error.message, "syntax error near unexpected token `;'");
+    }
+
+    #[test]
+    fn substitutions() {
+        assert_eq!(words("echo $(a  b)"), ["echo", "<a", "b>"]);
+        assert_eq!(words("echo \"$(a  b)\""), ["echo", "<a  b>"]);
+        assert_eq!(words("echo `a b`"), ["echo", "<a", "b>"]);
+        assert_eq!(
+            words("echo $(a $(b) 'c)')"),
+            ["echo", "<a", "$(b)", "'c)'>"]
+        );
+        assert_eq!(words("echo x$(a)y"), ["echo", "x<a>y"]);
+    }
+
+    #[test]
+    fn unquoted_expansions_split_on_ifs() {
+        let values = &[("X", " a  b\tc "), ("E", ""), ("P", "a:b::c:")];
+        assert_eq!(words_with(values, "echo $X"), ["echo", "a", "b", "c"]);
+        assert_eq!(words_with(values, "echo \"$X\""), ["echo", " a  b\tc "]);
+        assert_eq!(
+            words_with(values, "echo x${X}y"),
+            ["echo", "x", "a", "b", "c", "y"]
+        );
+        assert_eq!(words_with(values, "echo $E $E"), ["echo"]);
+        assert_eq!(words_with(values, "echo \"$E\" $E"), ["echo", ""]);
+        assert_eq!(words_with(values, "echo $P"), ["echo", "a:b::c:"]);
+    }
+
+    #[test]
+    fn custom_ifs() {
+        let values = &[("IFS", ":"), ("P", "a:b::c:"), ("X", "a b")];
+        assert_eq!(words_with(values, "echo $P"), ["echo", "a", "b", "", "c"]);
+        assert_eq!(words_with(values, "echo $X"), ["echo", "a b"]);
+        let values = &[("IFS", " :"), ("P", "a : b")];
+        assert_eq!(words_with(values, "echo $P"), ["echo", "a", "b"]);
+        let values = &[("IFS", ""), ("X", "a b")];
+        assert_eq!(words_with(values, "echo $X"), ["echo", "a b"]);
+        let values = &[("X", "a b")];
+        assert_eq!(
+            tokens_with(values, "A=$X")[0],
+            Token::Assignment {
+                name: "A".into(),
+                value: "a b".into()
+            }
+        );
+    }
+
+    #[test]
+    fn forced_overwrite() {
+        let redirect = |force| Token::Redirect {
+            kind: RedirectType::Stdout,
+            path: "f".into(),
+            append: false,
+            force,
+        };
+        assert_eq!(
+            tokens("a >|f > f"),
+            [Token::Arg("a".into()), redirect(true), redirect(false)]
+        );
+    }
+}
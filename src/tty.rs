@@ -0,0 +1,307 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    mem::ManuallyDrop,
+    os::{
+        raw::{c_int, c_short, c_ulong},
+        unix::io::FromRawFd,
+    },
+    process::{Command, Stdio},
+};
+
+/// A key press as read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Alt(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    Esc,
+}
+
+/// Puts the terminal into a mode where keys arrive one at a time without being echoed or
+/// turned into signals. The previous settings are restored when it is dropped.
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        // `-ixon` keeps Ctrl+S and Ctrl+Q from pausing and resuming output, so that Ctrl+S
+        // reaches the editor as forward search.
+        stty(&[
+            "-icanon", "-echo", "-isig", "-iexten", "-ixon", "min", "1", "time", "0",
+        ])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // There is nothing left to do about a terminal that can't be restored.
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` against the terminal on stdin, returning what it prints.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Decodes key presses from stdin. It reads the descriptor directly rather than through
+/// `io::stdin()`, whose buffer would hide whether the rest of an escape sequence has arrived.
+pub struct Keys {
+    stdin: ManuallyDrop<File>,
+}
+
+impl Keys {
+    pub fn new() -> Self {
+        // SAFETY: stdin stays open for the life of the shell, and `ManuallyDrop` keeps this
+        // handle from closing it.
+        let stdin = unsafe { File::from_raw_fd(0) };
+        Keys {
+            stdin: ManuallyDrop::new(stdin),
+        }
+    }
+
+    /// Reads the next key press, or `None` at the end of the input. Escape sequences the
+    /// editor has no use for are skipped.
+    pub fn next(&mut self) -> io::Result<Option<Key>> {
+        loop {
+            let Some(byte) = self.byte()? else {
+                return Ok(None);
+            };
+            let key = match byte {
+                b'\r' | b'\n' => Key::Enter,
+                b'\t' => Key::Tab,
+                0x7f | 0x08 => Key::Backspace,
+                0x1b => match self.escape()? {
+                    Some(key) => key,
+                    None => continue,
+                },
+                0x01..=0x1a => Key::Ctrl(char::from(b'a' + byte - 1)),
+                0x00..=0x7f => Key::Char(char::from(byte)),
+                _ => match self.utf8(byte)? {
+                    Some(c) => Key::Char(c),
+                    None => continue,
+                },
+            };
+            return Ok(Some(key));
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0];
+        loop {
+            match self.stdin.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Decodes what follows an escape byte. A lone escape is the Escape key itself, which
+    /// is told apart from the start of a sequence by nothing else arriving right away.
+    fn escape(&mut self) -> io::Result<Option<Key>> {
+        if !pending(ESCAPE_TIMEOUT_MS) {
+            return Ok(Some(Key::Esc));
+        }
+        match self.byte()? {
+            Some(b'[') => self.csi(),
+            Some(b'O') => Ok(match self.byte()? {
+                Some(b'A') => Some(Key::Up),
+                Some(b'B') => Some(Key::Down),
+                Some(b'C') => Some(Key::Right),
+                Some(b'D') => Some(Key::Left),
+                Some(b'H') => Some(Key::Home),
+                Some(b'F') => Some(Key::End),
+                _ => None,
+            }),
+            Some(byte @ 0x20..=0x7e) => Ok(Some(Key::Alt(char::from(byte)))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes a control sequence following `ESC [`, like `ESC [ A` or `ESC [ 3 ~`.
+    fn csi(&mut self) -> io::Result<Option<Key>> {
+        let mut params = String::new();
+        let last = loop {
+            match self.byte()? {
+                Some(byte @ 0x40..=0x7e) => break byte,
+                Some(byte) => params.push(char::from(byte)),
+                None => return Ok(None),
+            }
+        };
+        Ok(match (last, params.as_str()) {
+            (b'A', _) => Some(Key::Up),
+            (b'B', _) => Some(Key::Down),
+            (b'C', _) => Some(Key::Right),
+            (b'D', _) => Some(Key::Left),
+            (b'H', _) | (b'~', "1" | "7") => Some(Key::Home),
+            (b'F', _) | (b'~', "4" | "8") => Some(Key::End),
+            (b'~', "3") => Some(Key::Delete),
+            _ => None,
+        })
+    }
+
+    /// Reads the rest of a multi-byte UTF-8 character starting with `first`.
+    fn utf8(&mut self, first: u8) -> io::Result<Option<char>> {
+        let len = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Ok(None),
+        };
+        let mut bytes = vec![first];
+        for _ in 1..len {
+            match self.byte()? {
+                Some(byte) => bytes.push(byte),
+                None => return Ok(None),
+            }
+        }
+        Ok(std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|s| s.chars().next()))
+    }
+}
+
+/// How long to wait for the rest of an escape sequence before taking it for the Escape key.
+const ESCAPE_TIMEOUT_MS: c_int = 25;
+
+const POLLIN: c_short = 1;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+extern "C" {
+    #[link_name = "poll"]
+    fn sys_poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+/// Whether stdin has input ready within `timeout` milliseconds.
+fn pending(timeout: c_int) -> bool {
+    let mut fd = PollFd {
+        fd: 0,
+        events: POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fd` is a valid array of one `pollfd` for the duration of the call.
+    unsafe { sys_poll(&mut fd, 1, timeout) > 0 }
+}
+
+#[cfg(not(target_os = "macos"))]
+const TIOCGWINSZ: c_ulong = 0x5413;
+#[cfg(target_os = "macos")]
+const TIOCGWINSZ: c_ulong = 0x4008_7468;
+
+#[repr(C)]
+#[derive(Default)]
+struct WinSize {
+    rows: u16,
+    columns: u16,
+    x_pixels: u16,
+    y_pixels: u16,
+}
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+/// The width of the terminal on stdout, assuming the traditional 80 columns if it can't
+/// be told.
+pub fn columns() -> usize {
+    let mut size = WinSize::default();
+    // SAFETY: `TIOCGWINSZ` fills in a `winsize`, which `size` matches the layout of.
+    match unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut WinSize) } {
+        0 if size.columns > 0 => usize::from(size.columns),
+        _ => 80,
+    }
+}
+
+/// Where a cursor `offset` columns into text starting at the left edge of the terminal
+/// ends up, as a row and column, when lines wrap after `columns` columns.
+pub fn position(offset: usize, columns: usize) -> (usize, usize) {
+    (offset / columns, offset % columns)
+}
+
+/// Rings the terminal bell.
+pub fn bell() -> io::Result<()> {
+    let mut out = io::stdout();
+    out.write_all(b"\x07")?;
+    out.flush()
+}
+
+/// How many terminal columns `s` takes up. East Asian wide characters take two and
+/// combining marks none, which is close enough to what terminals do without a full table.
+pub fn width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036f | 0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_wrap_at_the_edge() {
+        assert_eq!(position(0, 80), (0, 0));
+        assert_eq!(position(79, 80), (0, 79));
+        assert_eq!(position(80, 80), (1, 0));
+        assert_eq!(position(165, 80), (2, 5));
+        assert_eq!(position(7, 3), (2, 1));
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("echo"), 4);
+        assert_eq!(width("é"), 1);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width("日本"), 4);
+        assert_eq!(width("한글"), 4);
+        assert_eq!(width(""), 0);
+    }
+}
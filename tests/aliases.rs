@@ -0,0 +1,38 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn aliases_are_defined_listed_and_used() {
+    let output = run(
+        "alias",
+        "alias ll='echo long' ls=\"ls -x\"\nalias\nalias ll\nll here\necho ll\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "alias ll='echo long'\nalias ls='ls -x'\nalias ll='echo long'\nlong here\nll\n"
+    );
+}
+
+#[test]
+fn unalias_removes_an_alias() {
+    let output = run(
+        "unalias",
+        "alias ll='echo long'\nunalias ll\nll\necho $?\nunalias nope\necho $?\n",
+    );
+    assert_eq!(output.stdout, "127\n1\n");
+    assert_eq!(
+        output.stderr,
+        "ll: command not found\nunalias: nope: not found\n"
+    );
+}
+
+#[test]
+fn self_referential_aliases_expand_once() {
+    let output = run(
+        "alias-loop",
+        "alias echo='echo again'\necho hi\nalias a=b b=a\na\n",
+    );
+    assert_eq!(output.stdout, "again hi\n");
+    assert_eq!(output.stderr, "a: command not found\n");
+}
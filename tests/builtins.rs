@@ -0,0 +1,213 @@
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use common::{run, run_in, temp_dir};
+
+#[test]
+fn type_reports_on_every_name() {
+    let dir = temp_dir("type");
+    let tool = dir.join("bin/tool");
+    fs::create_dir(dir.join("bin")).unwrap();
+    fs::write(&tool, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    let script = format!(
+        "PATH={}/bin:$PATH\nalias ll='ls -l'\ntype echo tool nosuch ll\necho $?\n",
+        dir.display()
+    );
+    let output = run_in(&dir, &script);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "echo is a shell builtin\ntool is {}\nll is aliased to `ls -l'\n1\n",
+            tool.display()
+        )
+    );
+    assert_eq!(output.stderr, "nosuch: not found\n");
+}
+
+#[test]
+fn read_assigns_a_line() {
+    let output = run(
+        "read",
+        "read a\none two three\necho \"[$a]\"\nread a b\none two three\necho \"[$a][$b]\"\nread a b c\none two\necho \"[$a][$b][$c]\"\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "[one two three]\n[one][two three]\n[one][two][]\n"
+    );
+}
+
+#[test]
+fn read_raw_keeps_backslashes() {
+    let output = run(
+        "read-raw",
+        "read x <<< 'a\\b'\necho \"$x\"\nread -r x <<< 'a\\b'\necho \"$x\"\nIFS=: read a b <<< x:y\necho \"$a $b\"\n",
+    );
+    assert_eq!(output.stdout, "ab\na\\b\nx y\n");
+}
+
+#[test]
+fn read_fails_at_end_of_input() {
+    let output = run("read-eof", "read x < /dev/null\necho $?\n");
+    assert_eq!(output.stdout, "1\n");
+}
+
+#[test]
+fn true_and_false_need_no_path() {
+    let output = run(
+        "true-false",
+        "PATH=\ntrue\necho $?\nfalse\necho $?\ntype true false\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "0\n1\ntrue is a shell builtin\nfalse is a shell builtin\n"
+    );
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn colon_ignores_its_arguments() {
+    let output = run(
+        "colon",
+        "false\n: these are ignored --help -x\necho $?\n: && echo ran\ntype :\n",
+    );
+    assert_eq!(output.stdout, "0\nran\n: is a shell builtin\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn test_checks_files() {
+    let dir = temp_dir("test-files");
+    fs::write(dir.join("file"), "").unwrap();
+    fs::create_dir(dir.join("dir")).unwrap();
+    let mut script = String::new();
+    for name in ["file", "dir", "nope"] {
+        for op in ["-e", "-f", "-d"] {
+            script.push_str(&format!("test {} {}\necho $?\n", op, name));
+        }
+    }
+    let output = run_in(&dir, &script);
+    assert_eq!(output.stdout, "0\n0\n1\n0\n1\n0\n1\n1\n1\n");
+}
+
+#[test]
+fn brackets_need_closing() {
+    let output = run(
+        "brackets",
+        "[ 2 -lt 10 ] && echo yes\n[ -n '' ] || echo no\n[ -n x\necho $?\n",
+    );
+    assert_eq!(output.stdout, "yes\nno\n2\n");
+    assert_eq!(output.stderr, "[: missing `]'\n");
+}
+
+#[test]
+fn shopt_sets_and_shows_options() {
+    let output = run(
+        "shopt",
+        "shopt no_empty_cmd_completion\necho $?\nshopt -s no_empty_cmd_completion\nshopt\necho $?\nshopt -u no_empty_cmd_completion\nshopt -s bogus\necho $?\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "no_empty_cmd_completion\toff\n1\nno_empty_cmd_completion\ton\n0\n1\n"
+    );
+    assert_eq!(output.stderr, "shopt: bogus: invalid shell option name\n");
+}
+
+#[test]
+fn env_lists_the_environment_sorted() {
+    let output = run("env-list", "export ZZ=last AA=first\nenv\n");
+    let lines: Vec<_> = output.stdout.lines().collect();
+    let mut sorted = lines.clone();
+    sorted.sort();
+    assert_eq!(lines, sorted);
+    assert!(lines.contains(&"AA=first") && lines.contains(&"ZZ=last"));
+}
+
+#[test]
+fn env_assignments_last_for_one_command() {
+    let output = run(
+        "env-run",
+        "env X=1 printenv X\necho \"[$X]\"\nenv X=1 Y=2 sh -c 'echo $X$Y'\nenv | grep -c ^X=\n",
+    );
+    assert_eq!(output.stdout, "1\n[]\n12\n0\n");
+}
+
+#[test]
+fn echo_flags() {
+    let output = run(
+        "echo-flags",
+        "echo -n x\necho -e 'a\\tb\\\\c\\0101'\necho -E 'a\\tb'\necho -- -n\necho -n -e 'y\\n'\necho x -n\necho -nq z\n",
+    );
+    assert_eq!(output.stdout, "xa\tb\\cA\na\\tb\n-- -n\ny\nx -n\n-nq z\n");
+}
+
+#[test]
+fn printf_formats_its_arguments() {
+    let output = run(
+        "printf",
+        "printf '%s-%s\\n' a b\nprintf '%s\\n' one two three\nprintf '%d|%x|%c\\n' 42 255 xyz\nprintf '%d\\n' nope\necho $?\n",
+    );
+    assert_eq!(output.stdout, "a-b\none\ntwo\nthree\n42|ff|x\n0\n1\n");
+    assert_eq!(output.stderr, "printf: nope: invalid number\n");
+}
+
+#[test]
+fn command_skips_aliases() {
+    let output = run(
+        "command",
+        "alias echo='echo aliased'\necho x\ncommand echo plain\n",
+    );
+    assert_eq!(output.stdout, "aliased x\nplain\n");
+}
+
+#[test]
+fn command_v_reports_the_resolution() {
+    let dir = temp_dir("command-v");
+    let tool = dir.join("bin/tool");
+    fs::create_dir(dir.join("bin")).unwrap();
+    fs::write(&tool, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    let script = format!(
+        "PATH={}/bin:$PATH\nalias ll='ls -l'\ncommand -v ll echo tool\necho $?\ncommand -v nosuch\necho $?\n",
+        dir.display()
+    );
+    let output = run_in(&dir, &script);
+    assert_eq!(
+        output.stdout,
+        format!("alias ll='ls -l'\necho\n{}\n0\n1\n", tool.display())
+    );
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn help_lists_every_builtin() {
+    let output = run("help", "help\n");
+    let names: Vec<_> = output
+        .stdout
+        .lines()
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "exit", "echo", "printf", "type", "pwd", "cd", "pushd", "popd", "dirs", "export",
+            "history", "fc", "jobs", "fg", "bg", "wait", "kill", "alias", "unalias", "unset",
+            "set", "shopt", "rehash", "read", "env", "true", "false", ":", "test", "source",
+            "command", "help",
+        ]
+    );
+    assert!(output
+        .stdout
+        .contains("cd [dir]                                     Change the current directory.\n"));
+}
+
+#[test]
+fn help_describes_one_builtin() {
+    let output = run("help-cd", "help cd\nhelp nosuch\necho $?\n");
+    assert_eq!(
+        output.stdout,
+        "cd: cd [dir]\n    Change the current directory.\n    Without DIR it goes to HOME, and `cd -' goes back to the previous directory,\n    OLDPWD. A leading ~ stands for HOME.\n1\n"
+    );
+    assert_eq!(output.stderr, "help: no help topics match `nosuch'\n");
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::{run, temp_dir, Terminal};
+
+#[test]
+fn large_output_is_streamed() {
+    let output = run("stream", "yes | head -n 100000\n");
+    assert_eq!(output.stdout.len(), 200000);
+    assert!(output.stdout.lines().all(|line| line == "y"));
+}
+
+#[test]
+fn stdout_and_stderr_keep_their_order() {
+    let output = run(
+        "stream-order",
+        "sh -c 'echo a; echo b >&2; echo c; echo d >&2' 2>&1 | cat\n",
+    );
+    assert_eq!(output.stdout, "a\nb\nc\nd\n");
+}
+
+#[test]
+fn stdout_and_stderr_interleave_at_the_terminal() {
+    let dir = temp_dir("stream-terminal");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["sh -c 'echo out1; echo err1 >&2; echo out2; echo err2 >&2'\r"]);
+    terminal.expect("err2");
+    // Both streams are the terminal itself, so they arrive in the order they are written.
+    let screen = terminal.screen();
+    assert!(
+        screen.contains("out1\r\nerr1\r\nout2\r\nerr2\r\n"),
+        "{:?}",
+        screen
+    );
+}
@@ -0,0 +1,354 @@
+//! Runs the shell for the integration tests, either with a script piped to it or at a
+//! pseudo-terminal with keys typed into it.
+#![allow(dead_code)]
+
+use std::{
+    env,
+    ffi::CStr,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    ops::Deref,
+    os::{
+        raw::{c_char, c_int, c_ulong},
+        unix::{
+            io::{AsRawFd, FromRawFd},
+            process::CommandExt,
+        },
+    },
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+extern "C" {
+    fn posix_openpt(flags: c_int) -> c_int;
+    fn grantpt(fd: c_int) -> c_int;
+    fn unlockpt(fd: c_int) -> c_int;
+    fn ptsname(fd: c_int) -> *const c_char;
+    fn setsid() -> c_int;
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+#[cfg(target_os = "linux")]
+const O_NOCTTY: c_int = 0o400;
+#[cfg(not(target_os = "linux"))]
+const O_NOCTTY: c_int = 0x20000;
+const O_RDWR: c_int = 2;
+#[cfg(target_os = "linux")]
+const TIOCSCTTY: c_ulong = 0x540e;
+#[cfg(not(target_os = "linux"))]
+const TIOCSCTTY: c_ulong = 0x2000_7461;
+#[cfg(target_os = "linux")]
+const TIOCSWINSZ: c_ulong = 0x5414;
+#[cfg(not(target_os = "linux"))]
+const TIOCSWINSZ: c_ulong = 0x8008_7467;
+
+#[repr(C)]
+struct WinSize {
+    rows: u16,
+    columns: u16,
+    x_pixels: u16,
+    y_pixels: u16,
+}
+
+/// How long a test waits for the shell before giving up on it.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a run of the shell printed and how it ended.
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: Option<i32>,
+}
+
+/// A directory of its own for a test to work in, which is removed along with everything in
+/// it once the test is done with it.
+pub struct TempDir(PathBuf);
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A directory of its own for a test to work in, emptied first. `name` keeps tests that run
+/// at the same time apart.
+pub fn temp_dir(name: &str) -> TempDir {
+    let dir = env::temp_dir().join(format!("shell-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+}
+
+/// The shell with empty prompts and a home of its own, so that neither the prompts nor the
+/// user's startup file end up in what a test sees.
+pub fn shell(home: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"));
+    command
+        .current_dir(home)
+        .env("HOME", home)
+        .env("PS1", "")
+        .env("PS2", "")
+        .env_remove("SHELLRC")
+        .env_remove("HISTFILE")
+        .env_remove("HISTCONTROL")
+        .env_remove("HISTSIZE")
+        .env_remove("CDPATH")
+        .env_remove("OLDPWD");
+    command
+}
+
+/// Pipes `script` to the shell, started in `dir`, and waits for it to finish.
+pub fn run_in(dir: &Path, script: &str) -> Output {
+    run_with(dir, &[], script)
+}
+
+/// Pipes `script` to the shell, started in `dir` with `env` added to its environment.
+pub fn run_with(dir: &Path, env: &[(&str, &str)], script: &str) -> Output {
+    let mut child = shell(dir)
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let script = script.to_string();
+    let writer = thread::spawn(move || {
+        // The shell may exit before it has read everything.
+        let _ = stdin.write_all(script.as_bytes());
+    });
+    let output = wait_with_timeout(child);
+    writer.join().unwrap();
+    output
+}
+
+/// Pipes `script` to the shell, started in a directory of its own named after `name`.
+pub fn run(name: &str, script: &str) -> Output {
+    run_in(&temp_dir(name), script)
+}
+
+fn wait_with_timeout(mut child: process::Child) -> Output {
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let out = thread::spawn(move || {
+        let mut s = String::new();
+        let _ = stdout.read_to_string(&mut s);
+        s
+    });
+    let err = thread::spawn(move || {
+        let mut s = String::new();
+        let _ = stderr.read_to_string(&mut s);
+        s
+    });
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status.code();
+        }
+        if start.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            panic!("the shell took too long");
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    Output {
+        stdout: out.join().unwrap(),
+        stderr: err.join().unwrap(),
+        status,
+    }
+}
+
+/// The shell running at a pseudo-terminal, as it would for someone typing at it.
+pub struct Terminal {
+    master: File,
+    /// The path of the terminal the shell has, for looking at its settings.
+    slave: String,
+    child: process::Child,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Terminal {
+    /// Starts the shell at a new terminal in `dir`, with `env` added to its environment.
+    pub fn start(dir: &Path, env: &[(&str, &str)]) -> Terminal {
+        // SAFETY: the descriptor returned is checked before it is used, and `ptsname`
+        // returns a terminated string that is copied before anything else can change it.
+        let (master, slave) = unsafe {
+            let fd = posix_openpt(O_RDWR | O_NOCTTY);
+            assert!(fd >= 0, "{}", io::Error::last_os_error());
+            assert_eq!(grantpt(fd), 0);
+            assert_eq!(unlockpt(fd), 0);
+            let name = CStr::from_ptr(ptsname(fd)).to_string_lossy().into_owned();
+            (File::from_raw_fd(fd), name)
+        };
+        let path = slave;
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+
+        let mut command = shell(dir);
+        command
+            .env("TERM", "xterm")
+            .envs(env.iter().copied())
+            .stdin(slave.try_clone().unwrap())
+            .stdout(slave.try_clone().unwrap())
+            .stderr(slave);
+        // SAFETY: `setsid` and `ioctl` are async-signal-safe, which is all that is allowed
+        // between `fork` and `exec`.
+        unsafe {
+            command.pre_exec(|| {
+                setsid();
+                ioctl(0, TIOCSCTTY, 0);
+                Ok(())
+            });
+        }
+        let child = command.spawn().unwrap();
+
+        let output = Arc::new(Mutex::new(vec![]));
+        let mut reader = master.try_clone().unwrap();
+        let collected = Arc::clone(&output);
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // Reading fails once the shell has exited and closed the terminal.
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                collected.lock().unwrap().extend_from_slice(&buf[..n]);
+            }
+        });
+        let terminal = Terminal {
+            master,
+            slave: path,
+            child,
+            output,
+        };
+        terminal.settle();
+        terminal
+    }
+
+    /// Makes the terminal `columns` wide.
+    pub fn set_columns(&self, columns: u16) {
+        let size = WinSize {
+            rows: 24,
+            columns,
+            x_pixels: 0,
+            y_pixels: 0,
+        };
+        // SAFETY: `TIOCSWINSZ` reads a `winsize`, which `size` matches the layout of.
+        let result = unsafe { ioctl(self.master.as_raw_fd(), TIOCSWINSZ, &size as *const WinSize) };
+        assert_eq!(result, 0, "{}", io::Error::last_os_error());
+    }
+
+    /// The terminal's settings as `stty -a` prints them.
+    pub fn settings(&self) -> String {
+        let slave = File::open(&self.slave).unwrap();
+        let output = Command::new("stty")
+            .arg("-a")
+            .stdin(slave)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    /// Types each of `keys` in turn, giving the shell time to act on each.
+    pub fn type_keys(&mut self, keys: &[&str]) {
+        for key in keys {
+            self.master.write_all(key.as_bytes()).unwrap();
+            self.settle();
+        }
+    }
+
+    /// Waits until the shell has printed something new and gone quiet again.
+    fn settle(&self) {
+        let start = Instant::now();
+        let mut seen = self.output.lock().unwrap().len();
+        let mut quiet_since = Instant::now();
+        while start.elapsed() < TIMEOUT {
+            thread::sleep(Duration::from_millis(20));
+            let len = self.output.lock().unwrap().len();
+            if len != seen {
+                seen = len;
+                quiet_since = Instant::now();
+            } else if quiet_since.elapsed() > Duration::from_millis(150) {
+                return;
+            }
+        }
+    }
+
+    /// Everything the shell has written to the terminal so far.
+    pub fn screen(&self) -> String {
+        String::from_utf8_lossy(&self.output.lock().unwrap()).into_owned()
+    }
+
+    /// The line as the shell last drew it, and how many columns into it the cursor was put.
+    /// With the empty prompt the line is just what has been typed.
+    pub fn line(&self) -> (String, usize) {
+        let screen = self.screen();
+        let start = screen.rfind("\r\x1b[J").expect("nothing was drawn");
+        let drawn = &screen[start + 4..];
+        let (text, moves) = drawn.split_once('\r').unwrap_or((drawn, ""));
+        let column = moves
+            .strip_prefix("\x1b[")
+            .and_then(|moves| moves.split_once('C'))
+            .map_or(0, |(n, _)| n.parse().unwrap());
+        (text.to_string(), column)
+    }
+
+    /// How many times the shell has rung the bell.
+    pub fn bells(&self) -> usize {
+        self.screen().matches('\x07').count()
+    }
+
+    /// Waits for `text` to be written, panicking with what was written if it never is.
+    pub fn expect(&self, text: &str) {
+        let start = Instant::now();
+        while !self.screen().contains(text) {
+            if start.elapsed() > TIMEOUT {
+                panic!("{:?} never appeared in {:?}", text, self.screen());
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Waits for the shell to exit, returning its status, or `None` if it is still running
+    /// after a while.
+    pub fn wait(&mut self) -> Option<i32> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.code();
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        None
+    }
+
+    /// Whether the shell is still running.
+    pub fn running(&mut self) -> bool {
+        self.child.try_wait().unwrap().is_none()
+    }
+
+    /// The process id of the shell.
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use std::{
+    env, fs,
+    os::unix::fs::{symlink, PermissionsExt},
+    path::Path,
+};
+
+use common::{temp_dir, Terminal};
+
+/// A shell at a terminal whose `PATH` is just `bin` in its own directory, which holds only
+/// the `stty` that the line editor needs.
+fn start_in(dir: &Path) -> Terminal {
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let path = env::var_os("PATH").unwrap_or_default();
+    let stty = env::split_paths(&path)
+        .map(|dir| dir.join("stty"))
+        .find(|stty| stty.exists())
+        .expect("stty isn't on PATH");
+    symlink(stty, bin.join("stty")).unwrap();
+    Terminal::start(dir, &[("PATH", bin.to_str().unwrap())])
+}
+
+#[test]
+fn builtins_complete_with_a_space() {
+    let dir = temp_dir("complete-builtin");
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["ty", "\t"]);
+    assert_eq!(terminal.line(), ("type ".to_string(), 5));
+}
+
+#[test]
+fn builtins_complete_from_any_case() {
+    let dir = temp_dir("complete-case");
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["TY", "\t"]);
+    assert_eq!(terminal.line(), ("type ".to_string(), 5));
+    terminal.type_keys(&["\x15", "Exp", "\t"]);
+    assert_eq!(terminal.line(), ("export ".to_string(), 7));
+}
+
+#[test]
+fn commands_complete_to_executables_only() {
+    let dir = temp_dir("complete-executables");
+    let mut terminal = start_in(&dir);
+    let bin = dir.join("bin");
+    fs::write(bin.join("myscript"), "#!/bin/sh\n").unwrap();
+    fs::set_permissions(bin.join("myscript"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(bin.join("myplain"), "").unwrap();
+    fs::create_dir(bin.join("mydir")).unwrap();
+    terminal.type_keys(&["my", "\t"]);
+    assert_eq!(terminal.line(), ("myscript ".to_string(), 9));
+}
+
+#[test]
+fn long_listings_ask_first() {
+    let dir = temp_dir("complete-ask");
+    for name in ["file1", "file2", "file3"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    let mut terminal = start_in(&dir);
+    // The first Tab fills in what the names share, and the next two ask for the list.
+    terminal.type_keys(&["COMPLETION_QUERY_ITEMS=2\r", "cat f", "\t", "\t", "\t"]);
+    terminal.expect("Display all 3 possibilities? (y or n)");
+    terminal.type_keys(&["n"]);
+    assert!(!terminal.screen().contains("file1  file2"));
+    assert_eq!(terminal.line(), ("cat file".to_string(), 8));
+    terminal.type_keys(&["\t", "\t", "y"]);
+    terminal.expect("file1  file2  file3\r\n");
+}
+
+#[test]
+fn short_listings_are_shown_at_once() {
+    let dir = temp_dir("complete-list");
+    for name in ["file1", "file2"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["cat f", "\t", "\t", "\t"]);
+    terminal.expect("file1  file2\r\n");
+    assert!(!terminal.screen().contains("possibilities"));
+    assert_eq!(terminal.line(), ("cat file".to_string(), 8));
+}
+
+#[test]
+fn tab_on_an_empty_line_inserts_nothing() {
+    let dir = temp_dir("complete-empty");
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["shopt -s no_empty_cmd_completion\r", "\t", "\t", "\t"]);
+    assert_eq!(terminal.bells(), 3);
+    assert!(!terminal.screen().contains("stty"));
+    terminal.type_keys(&["  ", "\t", "echo hi"]);
+    assert_eq!(terminal.line(), ("  echo hi".to_string(), 9));
+    terminal.type_keys(&["\r"]);
+    terminal.expect("hi\r\n");
+}
+
+#[test]
+fn tab_on_an_empty_line_lists_commands_by_default() {
+    let dir = temp_dir("complete-empty-list");
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["\t", "\t"]);
+    terminal.expect("stty");
+    assert_eq!(terminal.line(), ("".to_string(), 0));
+}
+
+#[test]
+fn words_complete_in_the_middle_of_the_line() {
+    let dir = temp_dir("complete-mid");
+    fs::write(dir.join("file.txt"), "").unwrap();
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["cat fi | wc -l", "\x1b[D", "\x1b[D", "\x1b[D", "\x1b[D"]);
+    terminal.type_keys(&["\x1b[D", "\x1b[D", "\x1b[D", "\x1b[D", "\t"]);
+    assert_eq!(terminal.line(), ("cat file.txt | wc -l".to_string(), 13));
+    terminal.type_keys(&["\x01", "\x1b[C", "\x1b[C", "\t"]);
+    assert_eq!(terminal.line(), ("cat file.txt | wc -l".to_string(), 2));
+    terminal.type_keys(&["\x05", "\x15", "ty cat", "\x01", "\x1b[C", "\x1b[C", "\t"]);
+    assert_eq!(terminal.line(), ("type cat".to_string(), 5));
+}
+
+#[test]
+fn a_second_word_completes_to_paths() {
+    let dir = temp_dir("complete-argument");
+    fs::write(dir.join("typescript"), "").unwrap();
+    let mut terminal = start_in(&dir);
+    terminal.type_keys(&["echo ty", "\t"]);
+    assert_eq!(terminal.line(), ("echo typescript ".to_string(), 16));
+}
+
+#[test]
+fn quoted_command_names_complete() {
+    let dir = temp_dir("complete-quoted");
+    let mut terminal = start_in(&dir);
+    let prog = dir.join("bin/my prog");
+    fs::write(&prog, "#!/bin/sh\necho ran my prog\n").unwrap();
+    fs::set_permissions(&prog, fs::Permissions::from_mode(0o755)).unwrap();
+    for (typed, completed) in [
+        ("'my p", "'my prog' "),
+        ("\"my p", "\"my prog\" "),
+        ("my\\ p", "my\\ prog "),
+    ] {
+        terminal.type_keys(&[typed, "\t"]);
+        let column = completed.chars().count();
+        assert_eq!(terminal.line(), (completed.to_string(), column));
+        terminal.type_keys(&["\x15"]);
+    }
+    terminal.type_keys(&["'my p", "\t", "\r"]);
+    terminal.expect("ran my prog\r\n");
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use common::{run, run_in, run_with, temp_dir};
+
+#[test]
+fn cd_dash_returns_to_the_previous_directory() {
+    let output = run("cd-dash", "cd /tmp\ncd /\ncd -\npwd\n");
+    assert_eq!(output.stdout, "/tmp\n/tmp\n");
+}
+
+#[test]
+fn cd_dash_needs_a_previous_directory() {
+    let output = run("cd-dash-unset", "cd -\necho $?\n");
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "cd: OLDPWD not set\n");
+}
+
+#[test]
+fn cdpath_is_searched_without_being_exported() {
+    let dir = temp_dir("cdpath");
+    fs::create_dir_all(dir.join("cdp/sub")).unwrap();
+    fs::create_dir(dir.join("elsewhere")).unwrap();
+    let script = format!("cd elsewhere\nCDPATH={}/cdp\ncd sub\npwd\n", dir.display());
+    let output = run_in(&dir, &script);
+    let sub = dir.join("cdp/sub");
+    assert_eq!(output.stdout, format!("{0}\n{0}\n", sub.display()));
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn cdpath_is_skipped_for_directories_here() {
+    let dir = temp_dir("cdpath-local");
+    fs::create_dir_all(dir.join("cdp/sub")).unwrap();
+    fs::create_dir(dir.join("sub")).unwrap();
+    let script = format!("CDPATH={}/cdp\ncd sub\npwd\n", dir.display());
+    let output = run_in(&dir, &script);
+    assert_eq!(output.stdout, format!("{}\n", dir.join("sub").display()));
+}
+
+#[test]
+fn cd_reports_why_it_failed() {
+    let dir = temp_dir("cd-errors");
+    fs::write(dir.join("file"), "").unwrap();
+    let output = run_in(&dir, "cd missing\necho $?\ncd file\necho $?\npwd\n");
+    assert_eq!(output.stdout, format!("1\n1\n{}\n", dir.display()));
+    assert_eq!(
+        output.stderr,
+        "cd: missing: No such file or directory\ncd: file: Not a directory\n"
+    );
+}
+
+#[test]
+fn cd_reports_permission_denied() {
+    let dir = temp_dir("cd-permission");
+    let locked = dir.join("locked");
+    fs::create_dir(&locked).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // Root gets in regardless, so there is nothing to see.
+    if fs::read_dir(&locked).is_ok() {
+        return;
+    }
+    let output = run_in(&dir, "cd locked\necho $?\n");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "cd: locked: Permission denied\n");
+}
+
+#[test]
+fn cd_normalizes_relative_paths() {
+    let dir = temp_dir("cd-relative");
+    fs::create_dir_all(dir.join("a/b")).unwrap();
+    let output = run_in(
+        &dir,
+        "cd a/./b/..\npwd\ncd ../a//b\npwd\nunset HOME\ncd\npwd\n",
+    );
+    assert_eq!(output.stdout, format!("{0}/a\n{0}/a/b\n/\n", dir.display()));
+}
+
+#[test]
+fn pushd_and_popd_round_trip() {
+    let output = run("pushd", "pushd /tmp\npushd /\ndirs\npopd\npwd\npopd\n");
+    assert_eq!(
+        output.stdout,
+        "/tmp ~\n/ /tmp ~\n/ /tmp ~\n/tmp ~\n/tmp\n~\n"
+    );
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn pushd_alone_swaps_the_top_two() {
+    let output = run("pushd-swap", "pushd /tmp\npushd /\npushd\npwd\n");
+    assert_eq!(output.stdout, "/tmp ~\n/ /tmp ~\n/tmp / ~\n/tmp\n");
+}
+
+#[test]
+fn popd_on_an_empty_stack_fails() {
+    let output = run("popd-empty", "popd\necho $?\ndirs\n");
+    assert_eq!(output.stdout, "1\n~\n");
+    assert_eq!(output.stderr, "popd: directory stack empty\n");
+}
+
+#[test]
+fn cd_keeps_pwd_and_oldpwd_current() {
+    let temp = temp_dir("pwd");
+    let dir = temp.canonicalize().unwrap();
+    let output = run_in(
+        &dir,
+        "cd /tmp\necho $PWD $OLDPWD\nsh -c 'echo $PWD $OLDPWD'\ncd /\necho $PWD $OLDPWD\n",
+    );
+    assert_eq!(
+        output.stdout,
+        format!("/tmp {0}\n/tmp {0}\n/ /tmp\n", dir.display())
+    );
+}
+
+#[test]
+fn a_stale_pwd_is_replaced_at_startup() {
+    let temp = temp_dir("pwd-stale");
+    let dir = temp.canonicalize().unwrap();
+    let output = run_with(&dir, &[("PWD", "/nonexistent")], "echo $PWD\n");
+    assert_eq!(output.stdout, format!("{}\n", dir.display()));
+    let output = run_with(&dir, &[("PWD", "/")], "echo $PWD\n");
+    assert_eq!(output.stdout, format!("{}\n", dir.display()));
+}
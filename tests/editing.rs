@@ -0,0 +1,341 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{temp_dir, Terminal};
+
+/// A shell at a terminal that has already run `commands`, for searching back through.
+fn with_history(dir: &Path, commands: &[&str]) -> Terminal {
+    let mut terminal = Terminal::start(dir, &[]);
+    for command in commands {
+        terminal.type_keys(&[command, "\r"]);
+    }
+    terminal
+}
+
+#[test]
+fn reverse_search_finds_older_matches() {
+    let dir = temp_dir("search");
+    let mut terminal = with_history(&dir, &["echo alpha", "echo beta", "echo alps"]);
+    terminal.type_keys(&["\x12", "al"]);
+    assert_eq!(terminal.line().0, "(reverse-i-search)`al': echo alps");
+    terminal.type_keys(&["\x12"]);
+    assert_eq!(terminal.line().0, "(reverse-i-search)`al': echo alpha");
+    terminal.type_keys(&["\r"]);
+    assert_eq!(terminal.line(), ("echo alpha".to_string(), 10));
+}
+
+#[test]
+fn reverse_search_rings_the_bell_without_a_match() {
+    let dir = temp_dir("search-none");
+    let mut terminal = with_history(&dir, &["echo alpha"]);
+    terminal.type_keys(&["\x12", "al"]);
+    assert_eq!(terminal.bells(), 0);
+    terminal.type_keys(&["z"]);
+    assert_eq!(terminal.bells(), 1);
+    assert_eq!(
+        terminal.line().0,
+        "(failed reverse-i-search)`alz': echo alpha"
+    );
+}
+
+#[test]
+fn forward_search_turns_a_reverse_search_around() {
+    let dir = temp_dir("search-forward");
+    let mut terminal = with_history(&dir, &["echo alpha", "echo beta", "echo alps"]);
+    terminal.type_keys(&["\x12", "al", "\x12"]);
+    assert_eq!(terminal.line().0, "(reverse-i-search)`al': echo alpha");
+    terminal.type_keys(&["\x13"]);
+    assert_eq!(terminal.line().0, "(i-search)`al': echo alps");
+    terminal.type_keys(&["\x13"]);
+    assert_eq!(terminal.line().0, "(failed i-search)`al': echo alps");
+    assert_eq!(terminal.bells(), 1);
+    terminal.type_keys(&["\r"]);
+    assert_eq!(terminal.line(), ("echo alps".to_string(), 9));
+}
+
+#[test]
+fn forward_search_starts_from_the_entry_browsed_to() {
+    let dir = temp_dir("search-from");
+    let mut terminal = with_history(&dir, &["echo alpha", "echo beta", "echo alps"]);
+    terminal.type_keys(&["\x1b[A", "\x1b[A", "\x1b[A", "\x13"]);
+    assert!(!terminal
+        .settings()
+        .split_whitespace()
+        .any(|flag| flag == "ixon"));
+    assert_eq!(terminal.line().0, "(i-search)`': ");
+    terminal.type_keys(&["al"]);
+    assert_eq!(terminal.line().0, "(i-search)`al': echo alpha");
+    terminal.type_keys(&["\x13"]);
+    assert_eq!(terminal.line().0, "(i-search)`al': echo alps");
+}
+
+#[test]
+fn escape_leaves_the_line_as_it_was() {
+    let dir = temp_dir("search-cancel");
+    let mut terminal = with_history(&dir, &["echo alpha"]);
+    terminal.type_keys(&["echo x", "\x12", "al", "\x1b"]);
+    assert_eq!(terminal.line(), ("echo x".to_string(), 6));
+}
+
+#[test]
+fn home_and_end_jump_to_the_ends_of_the_line() {
+    let dir = temp_dir("home-end");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["echo abc", "\x1b[H"]);
+    assert_eq!(terminal.line(), ("echo abc".to_string(), 0));
+    terminal.type_keys(&["\x1b[F"]);
+    assert_eq!(terminal.line(), ("echo abc".to_string(), 8));
+    terminal.type_keys(&["\x1b[1~", "X", "\x1b[4~", "Y"]);
+    assert_eq!(terminal.line(), ("Xecho abcY".to_string(), 10));
+}
+
+#[test]
+fn ctrl_a_and_ctrl_e_jump_to_the_ends_of_the_line() {
+    let dir = temp_dir("ctrl-a-e");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["echo abc", "\x1b[D", "\x01"]);
+    assert_eq!(terminal.line(), ("echo abc".to_string(), 0));
+    terminal.type_keys(&["\x1b[C", "\x05"]);
+    assert_eq!(terminal.line(), ("echo abc".to_string(), 8));
+    terminal.type_keys(&["\x01", "\x1b[C", "X"]);
+    assert_eq!(terminal.line(), ("eXcho abc".to_string(), 2));
+}
+
+#[test]
+fn ctrl_w_deletes_the_word_before_the_cursor() {
+    let dir = temp_dir("ctrl-w");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["foo bar", "\x17"]);
+    assert_eq!(terminal.line(), ("foo ".to_string(), 4));
+    terminal.type_keys(&["bar  ", "\x17"]);
+    assert_eq!(terminal.line(), ("foo ".to_string(), 4));
+    terminal.type_keys(&["baz", "\x1b[D", "\x17"]);
+    assert_eq!(terminal.line(), ("foo z".to_string(), 4));
+    terminal.type_keys(&["\x01", "\x17"]);
+    assert_eq!(terminal.line(), ("foo z".to_string(), 0));
+    assert_eq!(terminal.bells(), 1);
+}
+
+#[test]
+fn ctrl_k_deletes_to_the_end_of_the_line() {
+    let dir = temp_dir("ctrl-k");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["foo bar", "\x1b[D", "\x1b[D", "\x0b"]);
+    assert_eq!(terminal.line(), ("foo b".to_string(), 5));
+    terminal.type_keys(&["\x0b"]);
+    assert_eq!(terminal.line(), ("foo b".to_string(), 5));
+    assert_eq!(terminal.bells(), 0);
+}
+
+#[test]
+fn ctrl_u_deletes_back_to_the_start_of_the_line() {
+    let dir = temp_dir("ctrl-u");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["echo abc", "\x1b[D", "\x15"]);
+    assert_eq!(terminal.line(), ("c".to_string(), 0));
+    terminal.type_keys(&["\x15"]);
+    assert_eq!(terminal.line(), ("c".to_string(), 0));
+    assert_eq!(terminal.bells(), 1);
+    terminal.type_keys(&["\x05", "\x15"]);
+    assert_eq!(terminal.line(), (String::new(), 0));
+}
+
+#[test]
+fn multibyte_characters_are_edited_whole() {
+    let dir = temp_dir("utf8");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["echo é日本", "\x1b[D"]);
+    assert_eq!(terminal.line(), ("echo é日本".to_string(), 8));
+    terminal.type_keys(&["\x7f"]);
+    assert_eq!(terminal.line(), ("echo é本".to_string(), 6));
+    terminal.type_keys(&["ü", "\x1b[D", "\x1b[D", "\x1b[3~"]);
+    assert_eq!(terminal.line(), ("echo ü本".to_string(), 5));
+    terminal.type_keys(&["\x01", "\x1b[F", "\r"]);
+    terminal.expect("\r\nü本\r\n");
+}
+
+#[test]
+fn delete_removes_the_character_under_the_cursor() {
+    let dir = temp_dir("delete");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["echo abc", "\x1b[D", "\x1b[D", "\x1b[3~"]);
+    assert_eq!(terminal.line(), ("echo ac".to_string(), 6));
+    terminal.type_keys(&["\x1b[3~"]);
+    assert_eq!(terminal.line(), ("echo a".to_string(), 6));
+    assert_eq!(terminal.bells(), 0);
+    terminal.type_keys(&["\x1b[3~"]);
+    assert_eq!(terminal.line(), ("echo a".to_string(), 6));
+    assert_eq!(terminal.bells(), 1);
+}
+
+#[test]
+fn long_lines_wrap_onto_the_next_row() {
+    let dir = temp_dir("wrap");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.set_columns(10);
+    terminal.type_keys(&["echo abcdefghij"]);
+    assert!(terminal
+        .screen()
+        .ends_with("\r\x1b[Jecho abcdefghij\r\x1b[5C"));
+    terminal.type_keys(&["\x01"]);
+    assert!(terminal
+        .screen()
+        .ends_with("\x1b[1A\r\x1b[Jecho abcdefghij\x1b[1A\r"));
+    terminal.type_keys(&["\x1b[C"; 12]);
+    assert!(terminal
+        .screen()
+        .ends_with("\r\x1b[Jecho abcdefghij\r\x1b[2C"));
+    terminal.type_keys(&["\x1b[D"; 3]);
+    assert!(terminal
+        .screen()
+        .ends_with("\x1b[1A\r\x1b[Jecho abcdefghij\x1b[1A\r\x1b[9C"));
+    terminal.type_keys(&["Z", "\x05", "\r"]);
+    terminal.expect("\r\nabcdZefghij\r\n");
+}
+
+#[test]
+fn ctrl_l_clears_the_screen_and_keeps_the_line() {
+    let dir = temp_dir("ctrl-l");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["echo abc", "\x1b[D", "\x0c"]);
+    assert!(terminal
+        .screen()
+        .ends_with("\x1b[H\x1b[2J\r\x1b[Jecho abc\r\x1b[7C"));
+    assert_eq!(terminal.line(), ("echo abc".to_string(), 7));
+}
+
+#[test]
+fn ctrl_c_abandons_the_line() {
+    let dir = temp_dir("ctrl-c-line");
+    let mut terminal = Terminal::start(&dir, &[("PS1", "> ")]);
+    terminal.type_keys(&["echo abc", "\x1b[D", "\x03"]);
+    assert!(terminal.screen().contains("> echo abc\r\x1b[10C^C\r\n"));
+    assert_eq!(terminal.line(), ("> ".to_string(), 2));
+    terminal.type_keys(&["echo $?\r"]);
+    terminal.expect("\r\n130\r\n");
+}
+
+#[test]
+fn ctrl_c_forgets_a_pending_tab() {
+    let dir = temp_dir("ctrl-c-tab");
+    for name in ["file1", "file2"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["cat file", "\t", "\x03", "cat file", "\t"]);
+    assert!(!terminal.screen().contains("file1  file2"));
+    assert_eq!(terminal.line(), ("cat file".to_string(), 8));
+    terminal.type_keys(&["\t"]);
+    terminal.expect("file1  file2\r\n");
+}
+
+/// Whether the terminal is in the raw mode the line editor uses, rather than the canonical
+/// mode with echo that everything else expects.
+fn raw(terminal: &Terminal) -> bool {
+    let settings = terminal.settings();
+    let flags: Vec<_> = settings.split_whitespace().collect();
+    assert_eq!(flags.contains(&"-icanon"), flags.contains(&"-echo"));
+    flags.contains(&"-icanon")
+}
+
+#[test]
+fn raw_mode_only_lasts_while_editing() {
+    let dir = temp_dir("raw-mode");
+    let mut terminal = Terminal::start(&dir, &[]);
+    assert!(raw(&terminal));
+    terminal.type_keys(&["sleep 1\r"]);
+    assert!(!raw(&terminal));
+    assert!(terminal.wait().is_none());
+    assert!(raw(&terminal));
+}
+
+#[test]
+fn leaving_the_shell_restores_the_terminal() {
+    for (name, keys) in [("raw-exit", "exit 3\r"), ("raw-eof", "\x04")] {
+        let dir = temp_dir(name);
+        let mut terminal = Terminal::start(&dir, &[]);
+        terminal.type_keys(&[keys]);
+        assert!(terminal.wait().is_some());
+        assert!(!raw(&terminal), "{:?}", keys);
+    }
+}
+
+#[test]
+fn a_printed_expansion_is_one_up_arrow_away() {
+    let dir = temp_dir("print-recall");
+    let mut terminal = with_history(&dir, &["echo one", "!ec:p | tr o 0"]);
+    assert!(!terminal.screen().contains("0ne"));
+    terminal.type_keys(&["\x1b[A"]);
+    assert_eq!(terminal.line(), ("echo one | tr o 0".to_string(), 17));
+    terminal.type_keys(&["\r"]);
+    terminal.expect("0ne");
+}
+
+#[test]
+fn alt_f_and_alt_b_move_by_words() {
+    let dir = temp_dir("alt-words");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["  echo  one two  ", "\x01"]);
+    let mut columns = vec![];
+    for key in ["\x1bf"; 5].into_iter().chain(["\x1bb"; 5]) {
+        terminal.type_keys(&[key]);
+        columns.push(terminal.line().1);
+    }
+    assert_eq!(columns, [6, 11, 15, 17, 17, 12, 8, 2, 0, 0]);
+    assert_eq!(terminal.line().0, "  echo  one two  ");
+}
+
+#[test]
+fn alt_dot_inserts_last_words_going_back() {
+    let dir = temp_dir("alt-dot");
+    let mut terminal = with_history(&dir, &["echo alpha", "true", "printf 'beta\\n'"]);
+    terminal.type_keys(&["x ", "\x1b."]);
+    assert_eq!(terminal.line(), ("x 'beta\\n'".to_string(), 10));
+    terminal.type_keys(&["\x1b."]);
+    assert_eq!(terminal.line(), ("x true".to_string(), 6));
+    terminal.type_keys(&["\x1b."]);
+    assert_eq!(terminal.line(), ("x alpha".to_string(), 7));
+    terminal.type_keys(&["\x1b."]);
+    assert_eq!(terminal.line(), ("x alpha".to_string(), 7));
+    assert_eq!(terminal.bells(), 1);
+    terminal.type_keys(&[" ", "\x1b."]);
+    assert_eq!(terminal.line(), ("x alpha 'beta\\n'".to_string(), 16));
+}
+
+#[test]
+fn edits_while_browsing_leave_the_history_alone() {
+    let dir = temp_dir("history-edits");
+    let mut terminal = with_history(&dir, &["echo one", "echo two"]);
+    terminal.type_keys(&["\x1b[A", "X", "\x1b[A"]);
+    assert_eq!(terminal.line().0, "echo one");
+    terminal.type_keys(&["\x1b[B"]);
+    assert_eq!(terminal.line().0, "echo twoX");
+    terminal.type_keys(&["\x1b[A", "\r"]);
+    terminal.expect("one\r\n");
+    terminal.type_keys(&["\x1b[A", "\x1b[A"]);
+    assert_eq!(terminal.line().0, "echo two");
+    terminal.type_keys(&["\x15", "history\r"]);
+    terminal.expect("    4  history");
+    assert!(terminal
+        .screen()
+        .contains("    1  echo one\r\n    2  echo two\r\n    3  echo one\r\n    4  history\r\n"));
+}
+
+#[test]
+fn up_starts_from_the_end_after_a_skipped_command() {
+    let dir = temp_dir("history-skipped");
+    let mut terminal = Terminal::start(&dir, &[("HISTCONTROL", "ignoreboth")]);
+    for command in ["echo a", "echo b", "echo b", " echo c", ""] {
+        terminal.type_keys(&[command, "\r"]);
+    }
+    terminal.type_keys(&["\x1b[A"]);
+    assert_eq!(terminal.line().0, "echo b");
+    terminal.type_keys(&["\x1b[A"]);
+    assert_eq!(terminal.line().0, "echo a");
+    // Running a recalled entry that is then skipped as a repeat starts over at the end too.
+    terminal.type_keys(&["\x1b[B", "\r", "\x1b[A"]);
+    assert_eq!(terminal.line().0, "echo b");
+    terminal.type_keys(&["\x1b[A"]);
+    assert_eq!(terminal.line().0, "echo a");
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn command_substitution() {
+    let output = run(
+        "substitution",
+        "echo $(echo hi)\necho `echo back`\necho \"x$(echo ')')y\"\n",
+    );
+    assert_eq!(output.stdout, "hi\nback\nx)y\n");
+}
+
+#[test]
+fn command_substitution_nests() {
+    let output = run(
+        "substitution-nested",
+        "echo $(echo $(echo nested) more)\necho $(echo \"$(echo 'a  b')\")\n",
+    );
+    assert_eq!(output.stdout, "nested more\na b\n");
+}
+
+#[test]
+fn unquoted_substitutions_split() {
+    let output = run(
+        "substitution-split",
+        "printf '[%s]\\n' $(printf 'a  b\\n\\n')\nprintf '[%s]\\n' \"$(printf 'a  b\\n\\n')\"\n",
+    );
+    assert_eq!(output.stdout, "[a]\n[b]\n[a  b]\n");
+}
+
+#[test]
+fn unquoted_variables_split_on_ifs() {
+    let output = run(
+        "ifs",
+        "X='a  b'\nprintf '[%s]' $X \"$X\"\necho\nIFS=:\nP=a:b::c\nprintf '[%s]' $P\necho\nE=\nprintf '[%s]' $E x\necho\n",
+    );
+    assert_eq!(output.stdout, "[a][b][a  b]\n[a][b][][c]\n[x]\n");
+}
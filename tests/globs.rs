@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+
+use common::{run_in, temp_dir, TempDir};
+
+/// A directory holding a few files for the patterns to match.
+fn files(name: &str) -> TempDir {
+    let dir = temp_dir(name);
+    for file in [
+        "b.txt",
+        "a.txt",
+        "file1.log",
+        "file22.log",
+        "cat",
+        ".hidden.txt",
+    ] {
+        fs::write(dir.join(file), "").unwrap();
+    }
+    dir
+}
+
+#[test]
+fn patterns_expand_to_sorted_matches() {
+    let output = run_in(
+        &files("glob"),
+        "echo *.txt\necho file?.log\necho [abc]*\necho */\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "a.txt b.txt\nfile1.log\na.txt b.txt cat\n*/\n"
+    );
+}
+
+#[test]
+fn patterns_matching_nothing_stay_literal() {
+    let output = run_in(&files("glob-none"), "echo *.rs q?\n");
+    assert_eq!(output.stdout, "*.rs q?\n");
+}
+
+#[test]
+fn quoted_wildcards_do_not_expand() {
+    let output = run_in(&files("glob-quoted"), "echo '*.txt' \"*.txt\" \\*.txt\n");
+    assert_eq!(output.stdout, "*.txt *.txt *.txt\n");
+}
+
+#[test]
+fn hidden_files_need_a_leading_dot() {
+    let output = run_in(&files("glob-hidden"), "echo .*.txt\n");
+    assert_eq!(output.stdout, ".hidden.txt\n");
+}
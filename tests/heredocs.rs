@@ -0,0 +1,51 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn here_documents_feed_stdin() {
+    let output = run(
+        "heredoc",
+        "X=world\ncat <<EOF\nhello $X\n  two\nEOF\necho after\n",
+    );
+    assert_eq!(output.stdout, "hello world\n  two\nafter\n");
+}
+
+#[test]
+fn dash_strips_leading_tabs() {
+    let output = run(
+        "heredoc-tabs",
+        "cat <<-EOF\n\tone\n\t\ttwo\n  three\n\tEOF\n",
+    );
+    assert_eq!(output.stdout, "one\ntwo\n  three\n");
+}
+
+#[test]
+fn quoted_delimiters_turn_off_expansion() {
+    let output = run(
+        "heredoc-quoted",
+        "X=world\ncat <<'EOF'\nliteral $X $(echo no)\nEOF\ncat <<\"EOF\"\nalso $X\nEOF\ncat <<E\\OF\nand $X\nEOF\ncat <<EOF\nyes $(echo sub) \\$X\nEOF\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "literal $X $(echo no)\nalso $X\nand $X\nyes sub $X\n"
+    );
+}
+
+#[test]
+fn here_strings_feed_a_line_to_stdin() {
+    let output = run(
+        "herestring",
+        "grep foo <<< foobar\necho $?\ngrep foo <<< nothing\necho $?\ncat <<< ''\n",
+    );
+    assert_eq!(output.stdout, "foobar\n0\n1\n\n");
+}
+
+#[test]
+fn here_strings_are_expanded_like_words() {
+    let output = run(
+        "herestring-expand",
+        "V='a  b'\ncat <<< \"$V\"\ncat <<< '$V'\ncat <<< $V\ncat <<< x$(echo y)z\n",
+    );
+    assert_eq!(output.stdout, "a  b\n$V\na  b\nxyz\n");
+}
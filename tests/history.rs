@@ -0,0 +1,143 @@
+mod common;
+
+use std::fs;
+
+use common::{run, run_in, run_with, temp_dir};
+
+#[test]
+fn history_numbers_the_entries() {
+    let output = run("history-list", "echo a\necho b\nhistory\n");
+    assert_eq!(
+        output.stdout,
+        "a\nb\n    1  echo a\n    2  echo b\n    3  history\n"
+    );
+}
+
+#[test]
+fn history_limits_to_the_last_entries() {
+    let output = run("history-limit", "echo a\necho b\nhistory 2\nhistory 0\n");
+    assert_eq!(output.stdout, "a\nb\n    2  echo b\n    3  history 2\n");
+}
+
+#[test]
+fn history_clear() {
+    let output = run("history-clear", "echo a\nhistory -c\nhistory\n");
+    assert_eq!(output.stdout, "a\n    1  history\n");
+}
+
+#[test]
+fn histcontrol_needs_no_export() {
+    let output = run(
+        "histcontrol",
+        "HISTCONTROL=ignoredups\necho a\necho a\nhistory\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "a\na\n    1  HISTCONTROL=ignoredups\n    2  echo a\n    3  history\n"
+    );
+}
+
+#[test]
+fn histsize_caps_the_history() {
+    let output = run("histsize", "HISTSIZE=2\necho a\necho b\nhistory\n");
+    assert_eq!(output.stdout, "a\nb\n    1  echo b\n    2  history\n");
+}
+
+#[test]
+fn fc_lists_entries() {
+    let output = run("fc-list", "echo one\necho two\nfc -l\nfc -ln 1\n");
+    assert_eq!(
+        output.stdout,
+        "one\ntwo\n1\t echo one\n2\t echo two\n\t echo one\n\t echo two\n\t fc -l\n"
+    );
+}
+
+#[test]
+fn fc_runs_the_edited_commands() {
+    let output = run(
+        "fc-edit",
+        "echo one\nFCEDIT='sed -i s/one/uno/'\nfc 1\nhistory\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "one\necho uno\nuno\n    1  echo one\n    2  FCEDIT='sed -i s/one/uno/'\n    3  echo uno\n    4  history\n"
+    );
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn histfile_is_saved_on_exit_and_loaded_on_start() {
+    let dir = temp_dir("histfile");
+    let file = dir.join("history");
+    let env = [("HISTFILE", file.to_str().unwrap())];
+    run_with(&dir, &env, "echo one\necho two\n");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "echo one\necho two\n");
+    run_with(&dir, &env, "echo three\nexit\n");
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "echo one\necho two\necho three\nexit\n"
+    );
+    let output = run_with(&dir, &env, "history\n");
+    assert_eq!(
+        output.stdout,
+        "    1  echo one\n    2  echo two\n    3  echo three\n    4  exit\n    5  history\n"
+    );
+}
+
+#[test]
+fn history_references_are_printed_then_run() {
+    let output = run(
+        "history-expand",
+        "echo one\n!!\n!ec | tr o 0\n!42\necho $?\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "one\necho one\none\necho one | tr o 0\n0ne\n1\n"
+    );
+    assert_eq!(output.stderr, "!42: event not found\n");
+}
+
+#[test]
+fn print_modifier_shows_without_running() {
+    let output = run("history-print", "echo one\n!ec:p\n!!:p | tr o 0\nhistory\n");
+    assert_eq!(
+        output.stdout,
+        "one\necho one\necho one | tr o 0\n    1  echo one\n    2  echo one\n    3  echo one | tr o 0\n    4  history\n"
+    );
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn erasedups_keeps_the_latest_of_each_command() {
+    let output = run(
+        "history-erasedups",
+        "HISTCONTROL=erasedups\necho a\necho b\necho a\nhistory\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "a\nb\na\n    1  HISTCONTROL=erasedups\n    2  echo b\n    3  echo a\n    4  history\n"
+    );
+}
+
+#[test]
+fn history_files_are_written_read_and_appended_to() {
+    let dir = temp_dir("history-files");
+    let output = run_in(
+        &dir,
+        "echo a\nhistory -w saved\nhistory -c\nhistory -r saved\nhistory\nhistory -r nosuch\nhistory -w\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "a\n    1  history -r saved\n    2  echo a\n    3  history -w saved\n    4  history\n"
+    );
+    assert_eq!(
+        output.stderr,
+        "history: nosuch: No such file or directory\nhistory: HISTFILE: not set\n"
+    );
+
+    run_in(&dir, "echo a\nhistory -a added\necho b\nhistory -a added\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("added")).unwrap(),
+        "echo a\nhistory -a added\necho b\nhistory -a added\n"
+    );
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{run, temp_dir, Terminal};
+
+#[test]
+fn background_jobs_get_an_id_and_return_at_once() {
+    let start = Instant::now();
+    let output = run(
+        "background",
+        "sleep 5 >/dev/null 2>&1 &\necho after\nkill %1\n",
+    );
+    assert!(start.elapsed() < Duration::from_secs(3));
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert!(lines[0].starts_with("[1] "), "{:?}", lines);
+    assert!(lines[0][4..].parse::<u32>().is_ok());
+    assert_eq!(lines[1], "after");
+}
+
+#[test]
+fn background_builtins_run_in_a_copy_of_the_shell() {
+    let output = run(
+        "background-builtin",
+        "read x &\necho after\nwait\necho x=$x\ncd / &\nwait\necho $PWD\n",
+    );
+    let lines: Vec<_> = output
+        .stdout
+        .lines()
+        .filter(|l| !l.starts_with('['))
+        .collect();
+    assert_eq!(lines[..2], ["after", "x="]);
+    assert_ne!(lines[2], "/");
+}
+
+#[test]
+fn jobs_lists_each_job_with_its_state() {
+    let output = run("jobs-list", "sleep 5 &\nsleep 6 &\njobs\nkill %1 %2\n");
+    let lines: Vec<_> = output.stdout.lines().skip(2).take(2).collect();
+    assert_eq!(
+        lines,
+        [
+            "[1]-  Running                 sleep 5 &",
+            "[2]+  Running                 sleep 6 &"
+        ]
+    );
+}
+
+#[test]
+fn fg_waits_for_the_job() {
+    let start = Instant::now();
+    let output = run(
+        "jobs-fg",
+        "sh -c 'sleep 1; exit 3' &\nfg %1\necho status=$?\njobs\nfg %9\necho $?\n",
+    );
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    let lines: Vec<_> = output.stdout.lines().skip(1).collect();
+    assert_eq!(lines, ["sh -c 'sleep 1; exit 3'", "status=3", "1"]);
+    assert_eq!(output.stderr, "fg: %9: no such job\n");
+}
+
+#[test]
+fn kill_signals_jobs_and_processes() {
+    let output = run(
+        "kill",
+        "sleep 5 &\nkill -TERM %1\nwait %1\necho $?\nsh -c 'echo $$ > pid; exec sleep 5' &\nsleep 0.2\nread pid < pid\nkill -9 $pid\nwait %1\necho $?\n",
+    );
+    let lines: Vec<_> = output
+        .stdout
+        .lines()
+        .filter(|l| !l.starts_with('['))
+        .collect();
+    assert_eq!(lines, ["143", "137"]);
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn kill_names_signals() {
+    let output = run(
+        "kill-l",
+        "kill -l 15 9\nkill -l | grep -c TERM\nkill -FOO 1\necho $?\nkill %3\necho $?\n",
+    );
+    assert_eq!(output.stdout, "TERM\nKILL\n1\n1\n1\n");
+    assert_eq!(
+        output.stderr,
+        "kill: FOO: invalid signal specification\nkill: %3: no such job\n"
+    );
+}
+
+#[test]
+fn exit_warns_once_about_running_jobs() {
+    let dir = temp_dir("exit-jobs");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["sleep 5 &\r", "exit\r"]);
+    terminal.expect("There are running jobs.\r\n");
+    assert!(terminal.running());
+    terminal.type_keys(&["exit 4\r"]);
+    assert_eq!(terminal.wait(), Some(4));
+}
+
+#[test]
+fn another_command_rearms_the_exit_warning() {
+    let dir = temp_dir("exit-jobs-again");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["sleep 5\r", "\x1a", "exit\r"]);
+    terminal.expect("There are stopped jobs.\r\n");
+    terminal.type_keys(&["true\r", "\x04"]);
+    assert_eq!(
+        terminal.screen().matches("There are stopped jobs.").count(),
+        2
+    );
+    assert!(terminal.running());
+    terminal.type_keys(&["\x04"]);
+    assert!(terminal.wait().is_some());
+}
+
+#[test]
+fn wait_blocks_until_the_job_finishes() {
+    let start = Instant::now();
+    let output = run(
+        "wait",
+        "sh -c 'sleep 0.5; exit 3' &\nwait %1\necho $?\nsh -c 'sleep 0.5; exit 4' &\nsh -c 'exit 5' &\nwait\necho $?\njobs\n",
+    );
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    let lines: Vec<_> = output
+        .stdout
+        .lines()
+        .filter(|l| !l.starts_with('['))
+        .collect();
+    assert_eq!(lines, ["3", "0"]);
+}
+
+#[test]
+fn wait_takes_a_process_id() {
+    let output = run(
+        "wait-pid",
+        "sh -c 'echo $$ > pid; sleep 0.2; exit 6' &\nsleep 0.1\nread pid < pid\nwait $pid\necho $?\nwait %9\necho $?\nwait 99999\necho $?\n",
+    );
+    let lines: Vec<_> = output
+        .stdout
+        .lines()
+        .filter(|l| !l.starts_with('['))
+        .collect();
+    assert_eq!(lines, ["6", "127", "127"]);
+    assert_eq!(
+        output.stderr,
+        "wait: %9: no such job\nwait: pid 99999 is not a child of this shell\n"
+    );
+}
+
+#[test]
+fn background_takes_a_whole_and_or_list() {
+    let output = run(
+        "background-list",
+        "sleep 0.3 && echo second &\necho first\nfalse && echo no &\nwait\njobs\n",
+    );
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert!(lines[0].starts_with("[1] "), "{:?}", lines);
+    assert_eq!(lines[1], "first");
+    assert!(lines[2].starts_with("[2] "), "{:?}", lines);
+    assert_eq!(lines[3..], ["second"]);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn semicolons_run_each_command() {
+    let output = run("semicolon", "echo a; echo b\nfalse; echo c\necho d;\n");
+    assert_eq!(output.stdout, "a\nb\nc\nd\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn and_or_short_circuit() {
+    let output = run(
+        "and-or",
+        "true && echo a\nfalse && echo no\necho $?\nfalse || echo b\ntrue || echo no\necho $?\n",
+    );
+    assert_eq!(output.stdout, "a\n1\nb\n0\n");
+}
+
+#[test]
+fn and_or_associate_left_to_right() {
+    let output = run(
+        "and-or-chain",
+        "false && echo no || echo a\ntrue || echo no && echo b\nfalse || false\necho $?\n",
+    );
+    assert_eq!(output.stdout, "a\nb\n1\n");
+}
+
+#[test]
+fn pipes_bind_tighter_than_and_or() {
+    let output = run(
+        "precedence",
+        "echo a | cat && echo b | cat ; echo c\nfalse | true && echo d\ntrue | false && echo no || echo e | tr e E\n",
+    );
+    assert_eq!(output.stdout, "a\nb\nc\nd\nE\n");
+}
+
+#[test]
+fn semicolons_bind_loosest() {
+    let output = run(
+        "precedence-semicolon",
+        "false && echo no ; echo f\ntrue || echo no | cat && echo g\nfalse || false && echo no ; echo h\n",
+    );
+    assert_eq!(output.stdout, "f\ng\nh\n");
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn errexit_stops_at_the_first_failure() {
+    let output = run("errexit", "echo one\nset -e\nfalse\necho never\n");
+    assert_eq!(output.stdout, "one\n");
+    assert_eq!(output.status, Some(1));
+}
+
+#[test]
+fn failures_carry_on_without_errexit() {
+    let output = run(
+        "errexit-off",
+        "false\necho one\nset -e\nset +e\nfalse\necho two\n",
+    );
+    assert_eq!(output.stdout, "one\ntwo\n");
+    assert_eq!(output.status, Some(0));
+}
+
+#[test]
+fn errexit_ignores_tested_failures() {
+    let output = run(
+        "errexit-tested",
+        "set -e\nfalse || echo handled\nfalse && echo no\necho alive\n",
+    );
+    assert_eq!(output.stdout, "handled\nalive\n");
+}
+
+#[test]
+fn xtrace_prints_expanded_commands() {
+    let output = run(
+        "xtrace",
+        "X=world\nset -x\necho hello $X | tr a-z A-Z\nY=1 printenv Y\nset +x\necho quiet\n",
+    );
+    assert_eq!(output.stdout, "HELLO WORLD\n1\nquiet\n");
+    assert_eq!(
+        output.stderr,
+        "+ echo hello world\n+ tr a-z A-Z\n+ Y=1 printenv Y\n+ set +x\n"
+    );
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{run, run_in, temp_dir};
+
+#[test]
+fn builtin_reads_piped_input_mid_pipeline() {
+    let output = run(
+        "pipeline-middle",
+        "echo hi | command tr h H | cat\nprintf 'a\\nb\\n' | read first | cat\necho $?\n",
+    );
+    assert_eq!(output.stdout, "Hi\n0\n");
+}
+
+#[test]
+fn builtins_sharing_a_pipeline_leave_the_shell_alone() {
+    let dir = temp_dir("pipeline-subshell");
+    let script =
+        "echo x | exit 5\necho status=$?\necho foo | cd /\npwd\necho a | read v\necho v=$v\n";
+    let output = run_in(&dir, script);
+    assert_eq!(output.stdout, format!("status=5\n{}\nv=\n", dir.display()));
+    assert_eq!(output.status, Some(0));
+}
+
+#[test]
+fn builtin_output_larger_than_a_pipe() {
+    let output = run(
+        "pipeline-large",
+        "printf '%s\\n' {1..40000} | wc -l\nprintf '%s\\n' {1..40000} | cat | command wc -l\nprintf '%s\\n' {1..40000} | cat | read x\necho done\n",
+    );
+    assert_eq!(
+        output.stdout.split_whitespace().collect::<Vec<_>>(),
+        ["40000", "40000", "done"]
+    );
+}
+
+#[test]
+fn pipeline_status_is_the_last_command() {
+    let output = run(
+        "pipeline-status",
+        "false | true\necho $?\ntrue | false\necho $?\n",
+    );
+    assert_eq!(output.stdout, "0\n1\n");
+}
+
+#[test]
+fn pipestatus_keeps_every_stage() {
+    let output = run(
+        "pipestatus",
+        "sh -c 'exit 3' | sh -c 'exit 4' | true\necho ${PIPESTATUS[@]}\necho $PIPESTATUS\nfalse | true\necho ${PIPESTATUS[1]} $PIPESTATUS\n",
+    );
+    assert_eq!(output.stdout, "3 4 0\n0\n0 1\n");
+}
+
+#[test]
+fn empty_stages_are_syntax_errors() {
+    let output = run(
+        "pipeline-empty",
+        "| echo leading\necho $?\necho a | | cat\necho $?\necho after\necho a |",
+    );
+    assert_eq!(output.stdout, "2\n2\nafter\n");
+    assert_eq!(
+        output.stderr,
+        "syntax error near unexpected token `|' (column 1)\n\
+         syntax error near unexpected token `|' (column 10)\n\
+         syntax error: unexpected end of input after `|' (column 8)\n"
+    );
+    assert_eq!(output.status, Some(2));
+}
+
+#[test]
+fn an_empty_stage_keeps_the_whole_line_from_running() {
+    let output = run(
+        "pipeline-empty-later",
+        "echo ran || | cat\necho ran ; | cat\necho ran && echo b | | cat\n",
+    );
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        output.stderr,
+        "syntax error near unexpected token `|' (column 13)\n\
+         syntax error near unexpected token `|' (column 12)\n\
+         syntax error near unexpected token `|' (column 22)\n"
+    );
+}
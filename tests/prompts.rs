@@ -0,0 +1,47 @@
+mod common;
+
+use std::fs;
+
+use common::{run, temp_dir, Terminal};
+
+#[test]
+fn ps1_follows_the_current_directory() {
+    let dir = temp_dir("ps1");
+    fs::create_dir(dir.join("sub")).unwrap();
+    let mut terminal = Terminal::start(&dir, &[("PS1", "[\\w] ")]);
+    assert_eq!(terminal.line(), ("[~] ".to_string(), 4));
+    terminal.type_keys(&["cd sub\r"]);
+    assert_eq!(terminal.line(), ("[~/sub] ".to_string(), 8));
+    terminal.type_keys(&["cd /\r"]);
+    assert_eq!(terminal.line(), ("[/] ".to_string(), 4));
+}
+
+#[test]
+fn prompt_defaults_to_a_dollar() {
+    let dir = temp_dir("ps1-unset");
+    let mut terminal = Terminal::start(&dir, &[]);
+    terminal.type_keys(&["unset PS1\r"]);
+    assert_eq!(terminal.line(), ("$ ".to_string(), 2));
+}
+
+#[test]
+fn unfinished_lines_continue_on_the_next() {
+    let output = run(
+        "ps2",
+        "echo 'one\ntwo'\necho x |\ntr x y\necho a &&\necho b\n",
+    );
+    assert_eq!(output.stdout, "one\ntwo\ny\na\nb\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn ps2_prompts_for_the_rest() {
+    let dir = temp_dir("ps2-prompt");
+    let mut terminal = Terminal::start(&dir, &[("PS2", "more> ")]);
+    terminal.type_keys(&["echo 'a\r"]);
+    assert_eq!(terminal.line(), ("more> ".to_string(), 6));
+    terminal.type_keys(&["b' |\r"]);
+    assert_eq!(terminal.line(), ("more> ".to_string(), 6));
+    terminal.type_keys(&["tr a-z A-Z\r"]);
+    terminal.expect("A\r\nB\r\n");
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use std::fs;
+
+use common::{run_in, temp_dir};
+
+#[test]
+fn input_redirection() {
+    let dir = temp_dir("redirect-input");
+    fs::write(dir.join("in.txt"), "one\ntwo\nthree\n").unwrap();
+    let output = run_in(&dir, "wc -l < in.txt\ncat < in.txt | grep t\n");
+    assert_eq!(output.stdout, "3\ntwo\nthree\n");
+}
+
+#[test]
+fn missing_input_fails_only_that_command() {
+    let dir = temp_dir("redirect-missing");
+    let output = run_in(
+        &dir,
+        "cat < nofile; echo after=$?\ncat < nofile | wc -l\necho ${PIPESTATUS[@]}\n",
+    );
+    assert_eq!(output.stdout, "after=1\n0\n1 0\n");
+    assert_eq!(
+        output.stderr,
+        "nofile: No such file or directory\nnofile: No such file or directory\n"
+    );
+}
+
+#[test]
+fn failed_output_redirection_carries_on() {
+    let dir = temp_dir("redirect-unwritable");
+    let output = run_in(
+        &dir,
+        "echo hi >/nodir/f || echo fallback\necho hi >/nodir/f && echo no\necho done\n",
+    );
+    assert_eq!(output.stdout, "fallback\ndone\n");
+}
+
+#[test]
+fn targets_expand_home() {
+    let dir = temp_dir("redirect-home");
+    let output = run_in(&dir, "echo one > ~/tilde\necho two > $HOME/variable\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(fs::read_to_string(dir.join("tilde")).unwrap(), "one\n");
+    assert_eq!(fs::read_to_string(dir.join("variable")).unwrap(), "two\n");
+}
+
+#[test]
+fn targets_that_split_are_ambiguous() {
+    let dir = temp_dir("redirect-ambiguous");
+    let output = run_in(
+        &dir,
+        "F='a b'\necho hi > $F; echo $?\necho hi > $NOPE\necho hi > \"$F\"\nS=' s '\necho hi > $S\n",
+    );
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(
+        output.stderr,
+        "$F: ambiguous redirect\n$NOPE: ambiguous redirect\n"
+    );
+    assert!(!dir.join("a").exists());
+    assert_eq!(fs::read_to_string(dir.join("a b")).unwrap(), "hi\n");
+    assert_eq!(fs::read_to_string(dir.join("s")).unwrap(), "hi\n");
+}
+
+#[test]
+fn here_strings_are_not_split() {
+    let output = run_in(&temp_dir("here-string-split"), "F='a  b'\ncat <<< $F\n");
+    assert_eq!(output.stdout, "a  b\n");
+}
+
+#[test]
+fn stderr_joins_stdout_after_it_moves() {
+    let dir = temp_dir("duplicate-after");
+    let output = run_in(
+        &dir,
+        "sh -c 'echo out; echo err >&2' >both 2>&1\nsh -c 'echo out; echo err >&2' 2>&1 | tr a-z A-Z\n",
+    );
+    assert_eq!(output.stdout, "OUT\nERR\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(fs::read_to_string(dir.join("both")).unwrap(), "out\nerr\n");
+}
+
+#[test]
+fn stderr_joins_stdout_before_it_moves() {
+    let dir = temp_dir("duplicate-before");
+    let output = run_in(&dir, "sh -c 'echo out; echo err >&2' 2>&1 >out\n");
+    assert_eq!(output.stdout, "err\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(fs::read_to_string(dir.join("out")).unwrap(), "out\n");
+}
+
+#[test]
+fn builtin_errors_follow_stderr_redirection() {
+    let dir = temp_dir("builtin-stderr");
+    let output = run_in(&dir, "type nosuch 2>err\necho $?\n");
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(
+        fs::read_to_string(dir.join("err")).unwrap(),
+        "nosuch: not found\n"
+    );
+}
+
+#[test]
+fn builtins_send_both_streams_with_ampersand() {
+    let dir = temp_dir("builtin-both");
+    let output = run_in(&dir, "echo hi &>out\ntype nosuch &>err\n");
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+    assert_eq!(fs::read_to_string(dir.join("out")).unwrap(), "hi\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("err")).unwrap(),
+        "nosuch: not found\n"
+    );
+}
+
+#[test]
+fn appending_accumulates_and_overwriting_truncates() {
+    let dir = temp_dir("append");
+    let script = "echo a >out\necho b >>out\n\
+        type x 2>err\ntype y 2>>err\n\
+        echo c &>both\ntype z &>both\n";
+    let output = run_in(&dir, script);
+    assert_eq!(output.stdout, "");
+    let read = |name| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("out"), "a\nb\n");
+    assert_eq!(read("err"), "x: not found\ny: not found\n");
+    assert_eq!(read("both"), "z: not found\n");
+    run_in(&dir, "echo d >out\ntype ww 2>err\n");
+    assert_eq!(read("out"), "d\n");
+    assert_eq!(read("err"), "ww: not found\n");
+}
+
+#[test]
+fn a_redirection_alone_creates_the_file() {
+    let dir = temp_dir("redirect-alone");
+    let output = run_in(&dir, "> file\necho $?\necho 2>>\necho $?\n");
+    assert_eq!(output.stdout, "0\n2\n");
+    assert_eq!(
+        output.stderr,
+        "syntax error: expected filename after redirection (column 8)\n"
+    );
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "");
+}
+
+#[test]
+fn forced_overwrite_truncates() {
+    let dir = temp_dir("force-overwrite");
+    fs::write(dir.join("file"), "old contents\n").unwrap();
+    let output = run_in(&dir, "echo x >| file\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "x\n");
+}
+
+#[test]
+fn dev_null_swallows_output() {
+    let output = run_in(
+        &temp_dir("dev-null"),
+        "echo x >/dev/null\necho $?\nsh -c 'echo y' >/dev/null\ntype nosuch 2>/dev/null\n",
+    );
+    assert_eq!(output.stdout, "0\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn dev_stderr_reaches_stderr() {
+    let output = run_in(
+        &temp_dir("dev-stderr"),
+        "echo x >/dev/stderr\nsh -c 'echo y' >/dev/stderr\necho z 2>/dev/stdout >&2\n",
+    );
+    assert_eq!(output.stdout, "z\n");
+    assert_eq!(output.stderr, "x\ny\n");
+}
+
+#[test]
+fn every_stream_can_be_redirected_at_once() {
+    let dir = temp_dir("redirect-all");
+    fs::write(dir.join("in"), "from the file\n").unwrap();
+    let output = run_in(
+        &dir,
+        "sh -c 'cat; echo oops >&2' <in >out 2>err\nread line <in >out2 2>err2\necho $line\n",
+    );
+    assert_eq!(output.stdout, "from the file\n");
+    assert_eq!(output.stderr, "");
+    let read = |name| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("out"), "from the file\n");
+    assert_eq!(read("err"), "oops\n");
+    assert_eq!(read("out2"), "");
+    assert_eq!(read("err2"), "");
+}
+
+#[test]
+fn the_last_redirection_of_a_stream_wins() {
+    let dir = temp_dir("redirect-last");
+    let output = run_in(
+        &dir,
+        "echo builtin >a >b\nsh -c 'echo program' >c >d\ntype nosuch 2>e 2>f\n",
+    );
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+    let read = |name| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("a"), "");
+    assert_eq!(read("b"), "builtin\n");
+    assert_eq!(read("c"), "");
+    assert_eq!(read("d"), "program\n");
+    assert_eq!(read("e"), "");
+    assert_eq!(read("f"), "nosuch: not found\n");
+}
+
+#[test]
+fn noclobber_keeps_existing_files() {
+    let dir = temp_dir("noclobber");
+    fs::write(dir.join("existing"), "old\n").unwrap();
+    let read = |name| fs::read_to_string(dir.join(name)).unwrap();
+    let output = run_in(
+        &dir,
+        "set -C\necho x >existing\necho $?\necho new >new\necho y >/dev/null\necho $?\n",
+    );
+    assert_eq!(output.stdout, "1\n0\n");
+    assert_eq!(output.stderr, "existing: cannot overwrite existing file\n");
+    assert_eq!(read("existing"), "old\n");
+    assert_eq!(read("new"), "new\n");
+
+    run_in(&dir, "set -o noclobber\necho more >>existing\n");
+    assert_eq!(read("existing"), "old\nmore\n");
+    run_in(&dir, "set -C\necho forced >|existing\n");
+    assert_eq!(read("existing"), "forced\n");
+    run_in(&dir, "set -C\nset +C\necho plain >existing\n");
+    assert_eq!(read("existing"), "plain\n");
+}
+
+#[test]
+fn both_streams_append_with_ampersand() {
+    let dir = temp_dir("append-both");
+    fs::write(dir.join("log"), "start\n").unwrap();
+    let output = run_in(
+        &dir,
+        "sh -c 'echo out1; echo err1 >&2' &>>log\nsh -c 'echo out2; echo err2 >&2' &>>log\necho builtin &>>log\ntype nosuch &>>log\n",
+    );
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+    assert_eq!(
+        fs::read_to_string(dir.join("log")).unwrap(),
+        "start\nout1\nerr1\nout2\nerr2\nbuiltin\nnosuch: not found\n"
+    );
+}
@@ -0,0 +1,16 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{temp_dir, Terminal};
+
+#[test]
+fn ctrl_c_interrupts_the_foreground_command() {
+    let dir = temp_dir("sigint");
+    let mut terminal = Terminal::start(&dir, &[]);
+    let start = Instant::now();
+    terminal.type_keys(&["sleep 100\r", "\x03", "echo status=$?\r"]);
+    terminal.expect("status=130");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(terminal.running());
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use std::fs;
+
+use common::{run_in, temp_dir};
+
+#[test]
+fn sourced_definitions_stay_in_the_shell() {
+    let dir = temp_dir("source");
+    fs::write(dir.join("rc"), "GREETING=hello\nalias hi='echo hi'\n").unwrap();
+    let output = run_in(
+        &dir,
+        "source rc\necho $GREETING\nhi\nunset GREETING\n. ./rc\necho $GREETING\n",
+    );
+    assert_eq!(output.stdout, "hello\nhi\nhello\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn errors_name_the_line_and_carry_on() {
+    let dir = temp_dir("source-error");
+    fs::write(dir.join("rc"), "echo before\n| echo\necho after\n").unwrap();
+    let output = run_in(&dir, "source rc\nsource missing\necho $?\n");
+    assert_eq!(output.stdout, "before\nafter\n1\n");
+    assert_eq!(
+        output.stderr,
+        "rc: line 2: syntax error near unexpected token `|' (column 1)\nmissing: No such file or directory\n"
+    );
+}
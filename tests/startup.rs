@@ -0,0 +1,50 @@
+mod common;
+
+use std::fs;
+
+use common::{run_in, run_with, temp_dir, Terminal};
+
+#[test]
+fn shellrc_in_home_is_sourced() {
+    let dir = temp_dir("shellrc-home");
+    fs::write(
+        dir.join(".shellrc"),
+        "alias hi='echo hi'\nexport GREETING=hello\n",
+    )
+    .unwrap();
+    let output = run_in(&dir, "hi\nprintenv GREETING\n");
+    assert_eq!(output.stdout, "hi\nhello\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn shellrc_variable_names_the_file() {
+    let dir = temp_dir("shellrc-var");
+    let rc = dir.join("rc");
+    fs::write(&rc, "alias hi='echo from rc'\n").unwrap();
+    fs::write(dir.join(".shellrc"), "alias hi='echo from home'\n").unwrap();
+    let output = run_with(&dir, &[("SHELLRC", rc.to_str().unwrap())], "hi\n");
+    assert_eq!(output.stdout, "from rc\n");
+}
+
+#[test]
+fn missing_shellrc_is_ignored() {
+    let dir = temp_dir("shellrc-missing");
+    let output = run_with(&dir, &[("SHELLRC", "/nonexistent/rc")], "echo ok\n");
+    assert_eq!(output.stdout, "ok\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn shellrc_is_in_effect_at_the_first_prompt() {
+    let dir = temp_dir("shellrc-prompt");
+    fs::write(
+        dir.join(".shellrc"),
+        "PS1='rc> '\nalias hi='echo hi there'\n",
+    )
+    .unwrap();
+    let mut terminal = Terminal::start(&dir, &[]);
+    assert_eq!(terminal.line(), ("rc> ".to_string(), 4));
+    terminal.type_keys(&["hi\r"]);
+    terminal.expect("\r\nhi there\r\n");
+}
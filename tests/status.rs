@@ -0,0 +1,81 @@
+mod common;
+
+use common::{run, run_in, run_with, temp_dir};
+use std::{fs, os::unix::fs::PermissionsExt};
+
+#[test]
+fn last_status_is_kept_in_question_mark() {
+    let output = run(
+        "status",
+        "true\necho $?\nfalse\necho $?\nsh -c 'exit 7'\necho $?\necho $?\n",
+    );
+    assert_eq!(output.stdout, "0\n1\n7\n0\n");
+}
+
+#[test]
+fn command_not_found_is_127() {
+    let output = run("status-not-found", "nosuchcmd\necho $?\n");
+    assert_eq!(output.stdout, "127\n");
+    assert_eq!(output.stderr, "nosuchcmd: command not found\n");
+}
+
+#[test]
+fn syntax_errors_are_reported_with_their_column() {
+    let output = run(
+        "syntax-error",
+        "| echo\necho $?\necho >\necho 'unterminated\n",
+    );
+    assert_eq!(output.stdout, "2\n");
+    assert_eq!(
+        output.stderr,
+        "syntax error near unexpected token `|' (column 1)\n\
+         syntax error: expected filename after redirection (column 6)\n\
+         unexpected EOF while looking for matching `'' (column 6)\n"
+    );
+}
+
+#[test]
+fn a_file_that_is_not_executable_is_126() {
+    let dir = temp_dir("status-not-executable");
+    fs::create_dir(dir.join("bin")).unwrap();
+    let plain = dir.join("bin/plain");
+    fs::write(&plain, "echo hi\n").unwrap();
+    fs::set_permissions(&plain, fs::Permissions::from_mode(0o644)).unwrap();
+    let script = format!(
+        "PATH={}/bin:$PATH\nplain\necho $?\n./bin/plain\necho $?\n",
+        dir.display()
+    );
+    let output = run_in(&dir, &script);
+    assert_eq!(output.stdout, "126\n126\n");
+    assert_eq!(
+        output.stderr,
+        "plain: Permission denied\n./bin/plain: Permission denied\n"
+    );
+}
+
+#[test]
+fn exit_masks_its_status() {
+    let output = run("exit-300", "exit 300\necho after\n");
+    assert_eq!(output.status, Some(44));
+    assert_eq!(output.stdout, "");
+    assert_eq!(run("exit-negative", "exit -1\n").status, Some(255));
+    assert_eq!(run("exit-last", "false\nexit\n").status, Some(1));
+}
+
+#[test]
+fn exit_needs_a_number() {
+    let output = run("exit-abc", "exit abc\necho after\n");
+    assert_eq!(output.status, Some(2));
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "exit: abc: numeric argument required\n");
+}
+
+#[test]
+fn exit_saves_the_history_first() {
+    let dir = temp_dir("exit-history");
+    let file = dir.join("history");
+    let env = [("HISTFILE", file.to_str().unwrap())];
+    let output = run_with(&dir, &env, "echo one\nexit 300\necho after\n");
+    assert_eq!(output.status, Some(44));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "echo one\nexit 300\n");
+}
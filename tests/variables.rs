@@ -0,0 +1,59 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn exported_variables_reach_children() {
+    let output = run(
+        "export",
+        "export FOO=bar\nenv | grep ^FOO=\nBAZ=1\nenv | grep ^BAZ=\nexport BAZ\nenv | grep ^BAZ=\n",
+    );
+    assert_eq!(output.stdout, "FOO=bar\nBAZ=1\n");
+}
+
+#[test]
+fn bare_export_lists_the_exported_variables() {
+    let output = run("export-list", "export B=2 A=1\nexport | grep -E ' [AB]='\n");
+    assert_eq!(output.stdout, "declare -x A=\"1\"\ndeclare -x B=\"2\"\n");
+}
+
+#[test]
+fn export_rejects_a_missing_name() {
+    let output = run("export-invalid", "export =x\necho $?\n");
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "export: `=x': not a valid identifier\n");
+}
+
+#[test]
+fn unset_variables_expand_to_nothing() {
+    let output = run(
+        "unset",
+        "A=1\nexport B=2\necho $A $B\nunset A B\necho \"[$A$B]\"\nenv | grep -c ^B=\n",
+    );
+    assert_eq!(output.stdout, "1 2\n[]\n0\n");
+}
+
+#[test]
+fn unsetting_an_undefined_name_succeeds() {
+    let output = run("unset-undefined", "unset NOPE\necho $?\n");
+    assert_eq!(output.stdout, "0\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn assignments_before_a_command_last_for_it() {
+    let output = run(
+        "assignment-prefix",
+        "FOO=bar printenv FOO\necho \"[$FOO]\"\nA=1 B=2 sh -c 'echo $A$B'\nprintenv FOO || echo unset\n",
+    );
+    assert_eq!(output.stdout, "bar\n[]\n12\nunset\n");
+}
+
+#[test]
+fn assignments_alone_set_shell_variables() {
+    let output = run(
+        "assignment-only",
+        "FOO=bar BAZ=qux\necho $FOO $BAZ\nprintenv FOO || echo not exported\n",
+    );
+    assert_eq!(output.stdout, "bar qux\nnot exported\n");
+}